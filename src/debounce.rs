@@ -0,0 +1,195 @@
+//! Debounced button input
+//!
+//! [`Debounced`] wraps an [`InputPin`] connected to a mechanical button and filters out contact
+//! bounce. It has no notion of time itself: [`Debounced::tick`] must be called periodically, e.g.
+//! from a timer or SysTick interrupt, and all durations are expressed in ticks.
+
+use crate::hal::digital::v2::InputPin;
+
+/// Button event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The button was pressed
+    Pressed,
+    /// The button was released
+    Released,
+    /// The button has been held down for the long press duration
+    LongPress,
+}
+
+/// Pin level of a pressed button
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActiveLevel {
+    /// Pressing the button pulls the pin low
+    Low,
+    /// Pressing the button pulls the pin high
+    High,
+}
+
+/// Debounced button connected to `PIN`
+pub struct Debounced<PIN> {
+    pin: PIN,
+    active_level: ActiveLevel,
+    debounce_ticks: u16,
+    long_press_ticks: Option<u16>,
+    pressed: bool,
+    /// Number of consecutive ticks the pin level disagreed with `pressed`
+    unstable_ticks: u16,
+    /// Number of ticks the button has been held down, saturating at `long_press_ticks`
+    held_ticks: u16,
+}
+
+impl<PIN, E> Debounced<PIN>
+where
+    PIN: InputPin<Error = E>,
+{
+    /// Creates a debounced button, starting in the released state
+    ///
+    /// A change of the pin level is only reported once it has been stable for `debounce_ticks`
+    /// consecutive ticks.
+    pub fn new(pin: PIN, active_level: ActiveLevel, debounce_ticks: u16) -> Self {
+        Debounced {
+            pin,
+            active_level,
+            debounce_ticks: debounce_ticks.max(1),
+            long_press_ticks: None,
+            pressed: false,
+            unstable_ticks: 0,
+            held_ticks: 0,
+        }
+    }
+
+    /// Enables [`Event::LongPress`], reported once the button has been held down for `ticks`
+    /// ticks after the [`Event::Pressed`] event
+    pub fn with_long_press(mut self, ticks: u16) -> Self {
+        self.long_press_ticks = Some(ticks.max(1));
+        self
+    }
+
+    /// Samples the pin, returning an event if the debounced state changed
+    pub fn tick(&mut self) -> Result<Option<Event>, E> {
+        let active = match self.active_level {
+            ActiveLevel::Low => self.pin.is_low()?,
+            ActiveLevel::High => self.pin.is_high()?,
+        };
+
+        if active != self.pressed {
+            self.unstable_ticks += 1;
+            if self.unstable_ticks >= self.debounce_ticks {
+                self.pressed = active;
+                self.unstable_ticks = 0;
+                self.held_ticks = 0;
+                return Ok(Some(if active {
+                    Event::Pressed
+                } else {
+                    Event::Released
+                }));
+            }
+        } else {
+            self.unstable_ticks = 0;
+        }
+
+        if let (true, Some(long_press_ticks)) = (self.pressed, self.long_press_ticks) {
+            if self.held_ticks < long_press_ticks {
+                self.held_ticks += 1;
+                if self.held_ticks == long_press_ticks {
+                    return Ok(Some(Event::LongPress));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Returns the debounced state of the button
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Releases the pin
+    pub fn free(self) -> PIN {
+        self.pin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+    use core::convert::Infallible;
+
+    const H: bool = true;
+    const L: bool = false;
+
+    /// Input pin that returns a fixed sequence of levels, one per read
+    struct MockPin<'a> {
+        levels: &'a [bool],
+        index: Cell<usize>,
+    }
+
+    impl<'a> MockPin<'a> {
+        fn new(levels: &'a [bool]) -> Self {
+            MockPin {
+                levels,
+                index: Cell::new(0),
+            }
+        }
+    }
+
+    impl InputPin for MockPin<'_> {
+        type Error = Infallible;
+
+        fn is_high(&self) -> Result<bool, Self::Error> {
+            let i = self.index.get();
+            self.index.set(i + 1);
+            // keep the last level once the sequence is exhausted
+            Ok(self.levels[i.min(self.levels.len() - 1)])
+        }
+
+        fn is_low(&self) -> Result<bool, Self::Error> {
+            self.is_high().map(|high| !high)
+        }
+    }
+
+    fn events<const N: usize>(button: &mut Debounced<MockPin>) -> [Option<Event>; N] {
+        let mut events = [None; N];
+        for event in events.iter_mut() {
+            *event = button.tick().unwrap();
+        }
+        events
+    }
+
+    #[test]
+    fn bounce_is_filtered() {
+        // active low button bouncing on press and release
+        let levels = [H, L, H, L, L, L, L, H, L, H, H, H, H];
+        let mut button = Debounced::new(MockPin::new(&levels), ActiveLevel::Low, 3);
+
+        let events = events::<13>(&mut button);
+        assert_eq!(events[5], Some(Event::Pressed));
+        assert_eq!(events[11], Some(Event::Released));
+        assert_eq!(events.iter().filter(|e| e.is_some()).count(), 2);
+        assert!(!button.is_pressed());
+    }
+
+    #[test]
+    fn short_glitch_is_ignored() {
+        let levels = [L, L, H, L, L, H, H, L, L, L];
+        let mut button = Debounced::new(MockPin::new(&levels), ActiveLevel::High, 3);
+
+        assert_eq!(events::<10>(&mut button), [None; 10]);
+    }
+
+    #[test]
+    fn long_press_is_reported_once() {
+        let levels = [H, H, H, H, H, H, H, H, H, H, L, L];
+        let mut button =
+            Debounced::new(MockPin::new(&levels), ActiveLevel::High, 2).with_long_press(4);
+
+        let events = events::<12>(&mut button);
+        assert_eq!(events[1], Some(Event::Pressed));
+        assert_eq!(events[5], Some(Event::LongPress));
+        assert_eq!(events[11], Some(Event::Released));
+        assert_eq!(events.iter().filter(|e| e.is_some()).count(), 3);
+    }
+}
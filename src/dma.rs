@@ -0,0 +1,323 @@
+//! Direct memory access controller (DMA)
+//!
+//! The two DMA controllers each have seven channels. Which peripheral request a channel serves
+//! is selected through the DMA request multiplexer (DMAMUX), so any channel can be used for any
+//! peripheral. Peripherals start transfers on a channel and return a [`Transfer`] that owns the
+//! buffer, the channels and the peripheral until the transfer is done.
+
+use core::mem::ManuallyDrop;
+use core::ptr;
+use core::sync::atomic::{self, Ordering};
+
+use crate::pac::DMAMUX;
+use crate::rcc::AHB1;
+
+/// Extension trait to split a DMA peripheral into independent channels
+pub trait DmaExt {
+    /// The channels to split the DMA into
+    type Channels;
+
+    /// Splits the DMA into independent channels
+    fn split(self, ahb1: &mut AHB1) -> Self::Channels;
+}
+
+/// A peripheral that can be the subject of a DMA transfer
+pub trait TransferPayload {
+    /// Stops the peripheral side of the transfer
+    fn stop(&mut self);
+}
+
+pub(crate) mod sealed {
+    /// Transfer direction
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum Direction {
+        PeripheralToMemory,
+        MemoryToPeripheral,
+    }
+
+    pub trait Channel {
+        /// Disables the channel and configures it for byte transfers serving DMAMUX `request`
+        fn configure(&mut self, request: u8, direction: Direction, memory_increment: bool);
+        fn set_addresses(&mut self, peripheral: u32, memory: u32);
+        fn set_transfer_length(&mut self, len: u16);
+        fn start(&mut self);
+        fn stop(&mut self);
+        fn is_complete(&self) -> bool;
+        fn has_error(&self) -> bool;
+    }
+
+    /// The channel or channels used by a transfer
+    pub trait TransferChannels {
+        fn stop(&mut self);
+        fn is_complete(&self) -> bool;
+        fn has_error(&self) -> bool;
+    }
+}
+
+pub(crate) use sealed::Direction;
+
+/// DMA channel. This trait is sealed and cannot be implemented.
+pub trait Channel: sealed::Channel {}
+
+/// The channel or channels used by a [`Transfer`]. This trait is sealed and cannot be
+/// implemented.
+pub trait TransferChannels: sealed::TransferChannels {}
+
+impl<CH: Channel> TransferChannels for CH {}
+
+impl<RX: Channel, TX: Channel> TransferChannels for (RX, TX) {}
+
+impl<CH: Channel> sealed::TransferChannels for CH {
+    fn stop(&mut self) {
+        sealed::Channel::stop(self)
+    }
+
+    fn is_complete(&self) -> bool {
+        sealed::Channel::is_complete(self)
+    }
+
+    fn has_error(&self) -> bool {
+        sealed::Channel::has_error(self)
+    }
+}
+
+impl<RX: Channel, TX: Channel> sealed::TransferChannels for (RX, TX) {
+    fn stop(&mut self) {
+        sealed::Channel::stop(&mut self.0);
+        sealed::Channel::stop(&mut self.1);
+    }
+
+    fn is_complete(&self) -> bool {
+        sealed::Channel::is_complete(&self.0) && sealed::Channel::is_complete(&self.1)
+    }
+
+    fn has_error(&self) -> bool {
+        sealed::Channel::has_error(&self.0) || sealed::Channel::has_error(&self.1)
+    }
+}
+
+/// An ongoing DMA transfer that owns its buffer, channels and peripheral
+///
+/// Dropping the transfer before it is done aborts it.
+pub struct Transfer<BUF, CH, PAYLOAD>
+where
+    CH: TransferChannels,
+    PAYLOAD: TransferPayload,
+{
+    buffer: BUF,
+    channels: CH,
+    payload: PAYLOAD,
+}
+
+impl<BUF, CH, PAYLOAD> Transfer<BUF, CH, PAYLOAD>
+where
+    CH: TransferChannels,
+    PAYLOAD: TransferPayload,
+{
+    /// Wraps a transfer that was just started by `payload`
+    pub(crate) fn new(buffer: BUF, channels: CH, payload: PAYLOAD) -> Self {
+        Transfer {
+            buffer,
+            channels,
+            payload,
+        }
+    }
+
+    /// Returns `true` if the transfer completed or was aborted by a transfer error
+    pub fn is_done(&self) -> bool {
+        self.channels.is_complete() || self.channels.has_error()
+    }
+
+    /// Returns `true` if a transfer error occurred, e.g. because of an invalid buffer address
+    pub fn has_error(&self) -> bool {
+        self.channels.has_error()
+    }
+
+    /// Blocks until the transfer is done and releases the buffer, channels and peripheral
+    pub fn wait(self) -> (BUF, CH, PAYLOAD) {
+        while !self.is_done() {}
+
+        // don't let the compiler move buffer reads before the end of the transfer
+        atomic::compiler_fence(Ordering::Acquire);
+
+        let mut transfer = ManuallyDrop::new(self);
+        transfer.payload.stop();
+        transfer.channels.stop();
+
+        // NOTE(unsafe) every field is moved out exactly once and the transfer is not dropped
+        unsafe {
+            (
+                ptr::read(&transfer.buffer),
+                ptr::read(&transfer.channels),
+                ptr::read(&transfer.payload),
+            )
+        }
+    }
+}
+
+impl<BUF, CH, PAYLOAD> Drop for Transfer<BUF, CH, PAYLOAD>
+where
+    CH: TransferChannels,
+    PAYLOAD: TransferPayload,
+{
+    fn drop(&mut self) {
+        // stop the channels before the buffer is freed, then the DMA requests
+        self.channels.stop();
+        self.payload.stop();
+
+        atomic::compiler_fence(Ordering::Acquire);
+    }
+}
+
+macro_rules! dma {
+    ($DMAX:ident: ($dmaX:ident, $dmaXen:ident, $dmaXrst:ident, {
+        $($CX:ident: ($cX:ident, $i:expr, $ccrX:ident, $cndtrX:ident, $cparX:ident, $cmarX:ident,
+                      $cYcr:ident),)+
+    }),) => {
+        /// DMA channels
+        pub mod $dmaX {
+            use super::{sealed, DmaExt, Direction, DMAMUX};
+            use crate::rcc::AHB1;
+            use crate::pac::$DMAX;
+
+            /// DMA channels
+            pub struct Channels {
+                $(
+                    /// Channel
+                    pub $cX: $CX,
+                )+
+            }
+
+            $(
+                /// DMA channel
+                pub struct $CX {
+                    _0: (),
+                }
+
+                impl $CX {
+                    const ISR_OFFSET: u32 = 4 * ($i - 1);
+                }
+
+                impl sealed::Channel for $CX {
+                    fn configure(
+                        &mut self,
+                        request: u8,
+                        direction: Direction,
+                        memory_increment: bool,
+                    ) {
+                        // NOTE(unsafe) this channel owns its CCR, CNDTR, CPAR, CMAR and DMAMUX
+                        // channel registers
+                        let dma = unsafe { &*$DMAX::ptr() };
+                        let dmamux = unsafe { &*DMAMUX::ptr() };
+
+                        dma.$ccrX.write(|w| w.en().clear_bit());
+                        dmamux.$cYcr.write(|w| unsafe { w.dmareq_id().bits(request) });
+
+                        // PL: medium priority
+                        // MSIZE, PSIZE: 8-bit
+                        // PINC: peripheral address fixed
+                        // TCIE, HTIE, TEIE: no interrupts, the flags are polled
+                        dma.$ccrX.write(|w| unsafe {
+                            w.pl()
+                                .bits(0b01)
+                                .msize()
+                                .bits(0b00)
+                                .psize()
+                                .bits(0b00)
+                                .minc()
+                                .bit(memory_increment)
+                                .pinc()
+                                .clear_bit()
+                                .circ()
+                                .clear_bit()
+                                .dir()
+                                .bit(direction == Direction::MemoryToPeripheral)
+                        });
+                    }
+
+                    fn set_addresses(&mut self, peripheral: u32, memory: u32) {
+                        // NOTE(unsafe) see note above
+                        let dma = unsafe { &*$DMAX::ptr() };
+                        dma.$cparX.write(|w| unsafe { w.pa().bits(peripheral) });
+                        dma.$cmarX.write(|w| unsafe { w.ma().bits(memory) });
+                    }
+
+                    fn set_transfer_length(&mut self, len: u16) {
+                        // NOTE(unsafe) see note above
+                        let dma = unsafe { &*$DMAX::ptr() };
+                        dma.$cndtrX.write(|w| unsafe { w.ndt().bits(len as u32) });
+                    }
+
+                    fn start(&mut self) {
+                        // NOTE(unsafe) IFCR is write 1 to clear, this only clears this channel's
+                        // flags
+                        let dma = unsafe { &*$DMAX::ptr() };
+                        dma.ifcr.write(|w| unsafe { w.bits(1 << Self::ISR_OFFSET) });
+                        dma.$ccrX.modify(|_, w| w.en().set_bit());
+                    }
+
+                    fn stop(&mut self) {
+                        // NOTE(unsafe) see notes above
+                        let dma = unsafe { &*$DMAX::ptr() };
+                        dma.$ccrX.modify(|_, w| w.en().clear_bit());
+                        dma.ifcr.write(|w| unsafe { w.bits(1 << Self::ISR_OFFSET) });
+                    }
+
+                    fn is_complete(&self) -> bool {
+                        // NOTE(unsafe) atomic read with no side effects
+                        let isr = unsafe { (*$DMAX::ptr()).isr.read().bits() };
+                        isr & (1 << (Self::ISR_OFFSET + 1)) != 0
+                    }
+
+                    fn has_error(&self) -> bool {
+                        // NOTE(unsafe) atomic read with no side effects
+                        let isr = unsafe { (*$DMAX::ptr()).isr.read().bits() };
+                        isr & (1 << (Self::ISR_OFFSET + 3)) != 0
+                    }
+                }
+
+                impl super::Channel for $CX {}
+            )+
+
+            impl DmaExt for $DMAX {
+                type Channels = Channels;
+
+                fn split(self, ahb1: &mut AHB1) -> Channels {
+                    // enable or reset $DMAX, the multiplexer is shared with the other controller
+                    // and only enabled
+                    ahb1.enr().modify(|_, w| w.$dmaXen().set_bit().dmamux1en().set_bit());
+                    ahb1.rstr().modify(|_, w| w.$dmaXrst().set_bit());
+                    ahb1.rstr().modify(|_, w| w.$dmaXrst().clear_bit());
+
+                    Channels {
+                        $($cX: $CX { _0: () },)+
+                    }
+                }
+            }
+        }
+    };
+}
+
+dma! {
+    DMA1: (dma1, dma1en, dma1rst, {
+        C1: (c1, 1, ccr1, cndtr1, cpar1, cmar1, c0cr),
+        C2: (c2, 2, ccr2, cndtr2, cpar2, cmar2, c1cr),
+        C3: (c3, 3, ccr3, cndtr3, cpar3, cmar3, c2cr),
+        C4: (c4, 4, ccr4, cndtr4, cpar4, cmar4, c3cr),
+        C5: (c5, 5, ccr5, cndtr5, cpar5, cmar5, c4cr),
+        C6: (c6, 6, ccr6, cndtr6, cpar6, cmar6, c5cr),
+        C7: (c7, 7, ccr7, cndtr7, cpar7, cmar7, c6cr),
+    }),
+}
+
+dma! {
+    DMA2: (dma2, dma2en, dma2rst, {
+        C1: (c1, 1, ccr1, cndtr1, cpar1, cmar1, c7cr),
+        C2: (c2, 2, ccr2, cndtr2, cpar2, cmar2, c8cr),
+        C3: (c3, 3, ccr3, cndtr3, cpar3, cmar3, c9cr),
+        C4: (c4, 4, ccr4, cndtr4, cpar4, cmar4, c10cr),
+        C5: (c5, 5, ccr5, cndtr5, cpar5, cmar5, c11cr),
+        C6: (c6, 6, ccr6, cndtr6, cpar6, cmar6, c12cr),
+        C7: (c7, 7, ccr7, cndtr7, cpar7, cmar7, c13cr),
+    }),
+}
@@ -0,0 +1,193 @@
+//! Extended interrupts and events controller (EXTI)
+//!
+//! Backs the async `Wait` implementation of the GPIO input pins. Each of the 16 GPIO EXTI lines
+//! has its own waker; a waiting pin routes its line to its port, unmasks it, and is woken from
+//! the EXTI interrupt once the requested edge occurred.
+//!
+//! With the `rt` feature the EXTI interrupt handlers are provided by this module. Without it,
+//! call [`on_irq`] from the `EXTI0` to `EXTI4`, `EXTI9_5` and `EXTI15_10` handlers.
+//!
+//! Note that a line is shared by the pins with the same number on all ports, so only one of them
+//! can be waited on at a time.
+
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, Waker};
+
+use cortex_m::interrupt::{self, Mutex};
+use cortex_m::peripheral::NVIC;
+
+use crate::pac::{Interrupt, EXTI, SYSCFG};
+
+/// Signal edge
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Edge {
+    Rising,
+    Falling,
+    Any,
+}
+
+#[allow(clippy::declare_interior_mutable_const)] // only used to initialize `WAKERS`
+const NO_WAKER: Mutex<RefCell<Option<Waker>>> = Mutex::new(RefCell::new(None));
+static WAKERS: [Mutex<RefCell<Option<Waker>>>; 16] = [NO_WAKER; 16];
+
+/// Handles the pending GPIO EXTI lines, waking their waiting pins
+pub fn on_irq() {
+    // NOTE(unsafe) the pending register is write 1 to clear and the read-modify-write of the mask
+    // register is done in a critical section
+    let exti = unsafe { &*EXTI::ptr() };
+    let pending = exti.pr1.read().bits() & exti.imr1.read().bits() & 0xFFFF;
+
+    interrupt::free(|cs| {
+        // mask the lines, which also signals completion to the futures
+        exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !pending) });
+        exti.pr1.write(|w| unsafe { w.bits(pending) });
+
+        for (line, waker) in WAKERS.iter().enumerate() {
+            if pending & (1 << line) != 0 {
+                if let Some(waker) = waker.borrow(cs).borrow_mut().take() {
+                    waker.wake();
+                }
+            }
+        }
+    });
+}
+
+fn interrupt_for(line: u8) -> Interrupt {
+    match line {
+        0 => Interrupt::EXTI0,
+        1 => Interrupt::EXTI1,
+        2 => Interrupt::EXTI2,
+        3 => Interrupt::EXTI3,
+        4 => Interrupt::EXTI4,
+        5..=9 => Interrupt::EXTI9_5,
+        _ => Interrupt::EXTI15_10,
+    }
+}
+
+/// Future that completes on the next `edge` on a GPIO EXTI line
+pub(crate) struct ExtiInput {
+    line: u8,
+}
+
+impl ExtiInput {
+    /// Routes `line` to GPIO `port` (0 for GPIOA, 1 for GPIOB, ...) and arms it for `edge`
+    pub(crate) fn new(line: u8, port: u8, edge: Edge) -> Self {
+        let mask = 1 << line;
+        let offset = 4 * (line % 4);
+
+        interrupt::free(|_| {
+            // NOTE(unsafe) read-modify-writes in a critical section
+            let syscfg = unsafe { &*SYSCFG::ptr() };
+            let exti = unsafe { &*EXTI::ptr() };
+
+            let select = |bits: u32| (bits & !(0b111 << offset)) | ((port as u32) << offset);
+            match line / 4 {
+                0 => syscfg.exticr1.modify(|r, w| unsafe { w.bits(select(r.bits())) }),
+                1 => syscfg.exticr2.modify(|r, w| unsafe { w.bits(select(r.bits())) }),
+                2 => syscfg.exticr3.modify(|r, w| unsafe { w.bits(select(r.bits())) }),
+                _ => syscfg.exticr4.modify(|r, w| unsafe { w.bits(select(r.bits())) }),
+            }
+
+            let rising = edge != Edge::Falling;
+            let falling = edge != Edge::Rising;
+            exti.rtsr1.modify(|r, w| unsafe {
+                w.bits(if rising { r.bits() | mask } else { r.bits() & !mask })
+            });
+            exti.ftsr1.modify(|r, w| unsafe {
+                w.bits(if falling { r.bits() | mask } else { r.bits() & !mask })
+            });
+
+            exti.pr1.write(|w| unsafe { w.bits(mask) });
+            exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() | mask) });
+        });
+
+        // NOTE(unsafe) the EXTI handlers only touch the EXTI registers and the wakers, which are
+        // accessed in critical sections
+        unsafe { NVIC::unmask(interrupt_for(line)) };
+
+        ExtiInput { line }
+    }
+
+    fn is_masked(&self) -> bool {
+        // NOTE(unsafe) atomic read with no side effects
+        unsafe { (*EXTI::ptr()).imr1.read().bits() & (1 << self.line) == 0 }
+    }
+}
+
+impl Future for ExtiInput {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        interrupt::free(|cs| {
+            let mut waker = WAKERS[self.line as usize].borrow(cs).borrow_mut();
+            match waker.as_ref() {
+                Some(w) if w.will_wake(cx.waker()) => {}
+                _ => *waker = Some(cx.waker().clone()),
+            }
+        });
+
+        // the interrupt handler masks the line once the edge occurred
+        if self.is_masked() {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+impl Drop for ExtiInput {
+    fn drop(&mut self) {
+        let mask = 1 << self.line;
+        interrupt::free(|cs| {
+            // NOTE(unsafe) read-modify-writes in a critical section
+            let exti = unsafe { &*EXTI::ptr() };
+            exti.imr1.modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+            exti.rtsr1.modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+            exti.ftsr1.modify(|r, w| unsafe { w.bits(r.bits() & !mask) });
+
+            WAKERS[self.line as usize].borrow(cs).borrow_mut().take();
+        });
+    }
+}
+
+#[cfg(feature = "rt")]
+mod handlers {
+    use crate::pac::interrupt;
+
+    #[interrupt]
+    fn EXTI0() {
+        super::on_irq();
+    }
+
+    #[interrupt]
+    fn EXTI1() {
+        super::on_irq();
+    }
+
+    #[interrupt]
+    fn EXTI2() {
+        super::on_irq();
+    }
+
+    #[interrupt]
+    fn EXTI3() {
+        super::on_irq();
+    }
+
+    #[interrupt]
+    fn EXTI4() {
+        super::on_irq();
+    }
+
+    #[interrupt]
+    fn EXTI9_5() {
+        super::on_irq();
+    }
+
+    #[interrupt]
+    fn EXTI15_10() {
+        super::on_irq();
+    }
+}
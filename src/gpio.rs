@@ -1,4 +1,13 @@
 //! General Purpose Input / Output
+//!
+//! Pin modes can be changed either with the `into_*` methods, which take the opaque register
+//! proxies from the port `Parts`, or with their `*_atomic` counterparts, which don't. Both
+//! perform the read-modify-write of the shared port registers in a critical section, so the
+//! `*_atomic` methods can be used from anywhere, e.g. inside driver constructors or interrupt
+//! handlers.
+//!
+//! With the `async` feature, input pins implement the embedded-hal-async `Wait` trait on top of
+//! their EXTI line, see [`exti`](crate::exti).
 
 // Based on
 // https://github.com/japaric/stm32f30x-hal/blob/master/src/gpio.rs
@@ -113,25 +122,30 @@ macro_rules! doc_comment {
 }
 
 macro_rules! impl_into_af {
-    ($PXi:ident $AFR:ident $i:expr, $(($AF:ident, $NUM:expr, $NAME:ident));* $(;)?) => {
+    ($PXi:ident $AFR:ident $i:expr, $(($AF:ident, $NUM:expr, $NAME:ident, $NAME_ATOMIC:ident));* $(;)?) => {
         $(
             doc_comment! {
                 concat!("Configures the pin to serve as alternate function ", stringify!($NUM), " (", stringify!($AF), ")"),
                 pub fn $NAME(self, moder: &mut MODER, afr: &mut $AFR) -> $PXi<Alternate<$AF, MODE>> {
-                    const OFF_MODE: u32 = 2 * $i;
-                    const OFF_AFR: u32 = 4 * ($i % 8);
-                    const MODE: u32 = 0b10; // alternate function mode
-
-                    moder.moder().modify(|r, w| unsafe {
-                        w.bits((r.bits() & !(0b11 << OFF_MODE)) | (MODE << OFF_MODE))
-                    });
-                    afr.afr().modify(|r, w| unsafe {
-                        w.bits((r.bits() & !(0b1111 << OFF_AFR)) | ($NUM << OFF_AFR))
+                    let offset = 4 * ($i % 8);
+                    interrupt::free(|_| {
+                        afr.afr().modify(|r, w| unsafe {
+                            w.bits((r.bits() & !(0b1111 << offset)) | ($NUM << offset))
+                        })
                     });
+                    set_mode(moder.moder(), $i, 0b10); // alternate function mode
 
                     $PXi { _mode: PhantomData }
                 }
             }
+
+            doc_comment! {
+                concat!("Configures the pin to serve as alternate function ", stringify!($NUM), " (", stringify!($AF), ")",
+                        " without the `MODER` and `", stringify!($AFR), "` proxies"),
+                pub fn $NAME_ATOMIC(self) -> $PXi<Alternate<$AF, MODE>> {
+                    self.$NAME(&mut MODER { _0: () }, &mut $AFR { _0: () })
+                }
+            }
         )*
     }
 }
@@ -149,9 +163,12 @@ macro_rules! gpio {
             use core::marker::PhantomData;
             use core::convert::Infallible;
 
+            use cortex_m::interrupt;
             use crate::hal::digital::v2::{OutputPin, InputPin};
             use crate::stm32::{$gpioy, $GPIOX};
 
+            #[cfg(feature = "async")]
+            use crate::exti::{Edge, ExtiInput};
             use crate::rcc::AHB2;
             use super::{
 
@@ -269,6 +286,45 @@ macro_rules! gpio {
                 }
             }
 
+            // The registers below are shared by all pins of the port. Their read-modify-writes are
+            // done in a critical section, so that the `*_atomic` methods can stand in temporary
+            // proxies and reconfigure pins from any context.
+
+            fn set_mode(moder: &$gpioy::MODER, i: u32, mode: u32) {
+                let offset = 2 * i;
+                interrupt::free(|_| {
+                    moder.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
+                    })
+                });
+            }
+
+            fn set_pull(pupdr: &$gpioy::PUPDR, i: u32, pull: u32) {
+                let offset = 2 * i;
+                interrupt::free(|_| {
+                    pupdr.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(0b11 << offset)) | (pull << offset))
+                    })
+                });
+            }
+
+            fn set_output_type(otyper: &$gpioy::OTYPER, i: u32, open_drain: bool) {
+                interrupt::free(|_| {
+                    otyper.modify(|r, w| unsafe {
+                        w.bits((r.bits() & !(0b1 << i)) | ((open_drain as u32) << i))
+                    })
+                });
+            }
+
+            fn set_output_speed(i: u32, speed: Speed) {
+                let offset = 2 * i;
+                interrupt::free(|_| unsafe {
+                    (*$GPIOX::ptr()).ospeedr.modify(|r, w| {
+                        w.bits((r.bits() & !(0b11 << offset)) | ((speed as u32) << offset))
+                    })
+                });
+            }
+
             /// Partially erased pin
             pub struct $PXx<MODE> {
                 i: u8,
@@ -304,17 +360,9 @@ macro_rules! gpio {
                         moder: &mut MODER,
                         pupdr: &mut PUPDR,
                     ) -> $PXi<Input<Floating>> {
-                        let offset = 2 * $i;
-
-                        // input mode
-                        moder
-                            .moder()
-                            .modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << offset)) });
-
-                        // no pull-up or pull-down
-                        pupdr
-                            .pupdr()
-                            .modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << offset)) });
+                        // input mode, no pull-up or pull-down
+                        set_mode(moder.moder(), $i, 0b00);
+                        set_pull(pupdr.pupdr(), $i, 0b00);
 
                         $PXi { _mode: PhantomData }
                     }
@@ -325,17 +373,9 @@ macro_rules! gpio {
                         moder: &mut MODER,
                         pupdr: &mut PUPDR,
                     ) -> $PXi<Input<PullDown>> {
-                        let offset = 2 * $i;
-
-                        // input mode
-                        moder
-                            .moder()
-                            .modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << offset)) });
-
-                        // pull-down
-                        pupdr.pupdr().modify(|r, w| unsafe {
-                            w.bits((r.bits() & !(0b11 << offset)) | (0b10 << offset))
-                        });
+                        // input mode, pull-down
+                        set_mode(moder.moder(), $i, 0b00);
+                        set_pull(pupdr.pupdr(), $i, 0b10);
 
                         $PXi { _mode: PhantomData }
                     }
@@ -346,17 +386,9 @@ macro_rules! gpio {
                         moder: &mut MODER,
                         pupdr: &mut PUPDR,
                     ) -> $PXi<Input<PullUp>> {
-                        let offset = 2 * $i;
-
-                        // input mode
-                        moder
-                            .moder()
-                            .modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << offset)) });
-
-                        // pull-up
-                        pupdr.pupdr().modify(|r, w| unsafe {
-                            w.bits((r.bits() & !(0b11 << offset)) | (0b01 << offset))
-                        });
+                        // input mode, pull-up
+                        set_mode(moder.moder(), $i, 0b00);
+                        set_pull(pupdr.pupdr(), $i, 0b01);
 
                         $PXi { _mode: PhantomData }
                     }
@@ -367,18 +399,9 @@ macro_rules! gpio {
                         moder: &mut MODER,
                         otyper: &mut OTYPER,
                     ) -> $PXi<Output<OpenDrain>> {
-                        let offset = 2 * $i;
-
-                        // general purpose output mode
-                        let mode = 0b01;
-                        moder.moder().modify(|r, w| unsafe {
-                            w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
-                        });
-
-                        // open drain output
-                        otyper
-                            .otyper()
-                            .modify(|r, w| unsafe { w.bits(r.bits() | (0b1 << $i)) });
+                        // general purpose output mode, open drain
+                        set_mode(moder.moder(), $i, 0b01);
+                        set_output_type(otyper.otyper(), $i, true);
 
                         $PXi { _mode: PhantomData }
                     }
@@ -411,61 +434,89 @@ macro_rules! gpio {
                             State::Low => res.set_low().unwrap(),
                         }
 
-                        let offset = 2 * $i;
-
-                        // general purpose output mode
-                        let mode = 0b01;
-                        moder.moder().modify(|r, w| unsafe {
-                            w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
-                        });
-
-                        // push pull output
-                        otyper
-                            .otyper()
-                            .modify(|r, w| unsafe { w.bits(r.bits() & !(0b1 << $i)) });
+                        // general purpose output mode, push pull
+                        set_mode(moder.moder(), $i, 0b01);
+                        set_output_type(otyper.otyper(), $i, false);
 
                         res
                     }
 
-                        /// Configures the pin to operate as analog.
-                        /// This mode is suitable when the pin is connected to the DAC or ADC,
-                        /// COMP, OPAMP.
-                        pub fn into_analog(
+                    /// Configures the pin to operate as analog.
+                    /// This mode is suitable when the pin is connected to the DAC or ADC,
+                    /// COMP, OPAMP.
+                    pub fn into_analog(
                         self,
                         moder: &mut MODER,
                         pupdr: &mut PUPDR,
                     ) -> $PXi<Analog> {
-                        let offset = 2 * $i;
-
-                        // analog mode
-                        let mode = 0b11;
-                        moder.moder().modify(|r, w| unsafe {
-                            w.bits((r.bits() & !(0b11 << offset)) | (mode << offset))
-                        });
-
-                        // no pull-up or pull-down
-                        pupdr
-                            .pupdr()
-                            .modify(|r, w| unsafe { w.bits(r.bits() & !(0b11 << offset)) });
+                        // analog mode, no pull-up or pull-down
+                        set_mode(moder.moder(), $i, 0b11);
+                        set_pull(pupdr.pupdr(), $i, 0b00);
+
                         $PXi { _mode: PhantomData }
                     }
 
+                    /// Configures the pin to operate as a floating input pin, without the
+                    /// `MODER` and `PUPDR` proxies
+                    pub fn into_floating_input_atomic(self) -> $PXi<Input<Floating>> {
+                        self.into_floating_input(&mut MODER { _0: () }, &mut PUPDR { _0: () })
+                    }
+
+                    /// Configures the pin to operate as a pulled down input pin, without the
+                    /// `MODER` and `PUPDR` proxies
+                    pub fn into_pull_down_input_atomic(self) -> $PXi<Input<PullDown>> {
+                        self.into_pull_down_input(&mut MODER { _0: () }, &mut PUPDR { _0: () })
+                    }
+
+                    /// Configures the pin to operate as a pulled up input pin, without the
+                    /// `MODER` and `PUPDR` proxies
+                    pub fn into_pull_up_input_atomic(self) -> $PXi<Input<PullUp>> {
+                        self.into_pull_up_input(&mut MODER { _0: () }, &mut PUPDR { _0: () })
+                    }
+
+                    /// Configures the pin to operate as an open drain output pin, without the
+                    /// `MODER` and `OTYPER` proxies
+                    pub fn into_open_drain_output_atomic(self) -> $PXi<Output<OpenDrain>> {
+                        self.into_open_drain_output(&mut MODER { _0: () }, &mut OTYPER { _0: () })
+                    }
+
+                    /// Configures the pin to operate as an push pull output pin, without the
+                    /// `MODER` and `OTYPER` proxies
+                    /// Initial state will be low
+                    pub fn into_push_pull_output_atomic(self) -> $PXi<Output<PushPull>> {
+                        self.into_push_pull_output_with_state_atomic(State::Low)
+                    }
+
+                    /// Configures the pin to operate as an push pull output pin, without the
+                    /// `MODER` and `OTYPER` proxies
+                    /// Initial state can be chosen to be high or low
+                    pub fn into_push_pull_output_with_state_atomic(
+                        self,
+                        initial_state: State,
+                    ) -> $PXi<Output<PushPull>> {
+                        self.into_push_pull_output_with_state(
+                            &mut MODER { _0: () },
+                            &mut OTYPER { _0: () },
+                            initial_state,
+                        )
+                    }
+
+                    /// Configures the pin to operate as analog, without the `MODER` and `PUPDR`
+                    /// proxies
+                    pub fn into_analog_atomic(self) -> $PXi<Analog> {
+                        self.into_analog(&mut MODER { _0: () }, &mut PUPDR { _0: () })
+                    }
                 }
 
                 impl $PXi<Output<OpenDrain>> {
                     /// Enables / disables the internal pull up
                     pub fn internal_pull_up(&mut self, pupdr: &mut PUPDR, on: bool) {
-                        let offset = 2 * $i;
-
-                        pupdr.pupdr().modify(|r, w| unsafe {
-                            w.bits(
-                                (r.bits() & !(0b11 << offset)) | if on {
-                                    0b01 << offset
-                                } else {
-                                    0
-                                },
-                            )
-                        });
+                        set_pull(pupdr.pupdr(), $i, if on { 0b01 } else { 0b00 });
+                    }
+
+                    /// Enables / disables the internal pull up, without the `PUPDR` proxy
+                    pub fn internal_pull_up_atomic(&mut self, on: bool) {
+                        self.internal_pull_up(&mut PUPDR { _0: () }, on)
                     }
                 }
 
@@ -483,13 +534,7 @@ macro_rules! gpio {
 
                     /// Set pin speed
                     pub fn set_speed(self, speed: Speed) -> Self {
-                        let offset = 2 * $i;
-
-                        unsafe {
-                            &(*$GPIOX::ptr()).ospeedr.modify(|r, w| {
-                                w.bits((r.bits() & !(0b11 << offset)) | ((speed as u32) << offset))
-                            })
-                        };
+                        set_output_speed($i, speed);
 
                         self
                     }
@@ -498,38 +543,21 @@ macro_rules! gpio {
                 impl<AF, MODE> $PXi<Alternate<AF, MODE>> {
                     /// Set pin speed
                     pub fn set_speed(self, speed: Speed) -> Self {
-                        let offset = 2 * $i;
-
-                        unsafe {
-                            &(*$GPIOX::ptr()).ospeedr.modify(|r, w| {
-                                w.bits((r.bits() & !(0b11 << offset)) | ((speed as u32) << offset))
-                            })
-                        };
+                        set_output_speed($i, speed);
 
                         self
                     }
 
                     /// Enables / disables the internal pull up
                     pub fn internal_pull_up(self, on: bool) -> Self {
-                        let offset = 2 * $i;
-                        let value = if on { 0b01 } else { 0b00 };
-                        unsafe {
-                            &(*$GPIOX::ptr()).pupdr.modify(|r, w| {
-                                w.bits((r.bits() & !(0b11 << offset)) | (value << offset))
-                            })
-                        };
+                        set_pull(PUPDR { _0: () }.pupdr(), $i, if on { 0b01 } else { 0b00 });
 
                         self
                     }
 
                     /// Turns pin alternate configuration pin into open drain
                     pub fn set_open_drain(self) -> $PXi<AlternateOD<AF, MODE>> {
-                        let offset = $i;
-                        unsafe {
-                            &(*$GPIOX::ptr()).otyper.modify(|r, w| {
-                                w.bits(r.bits() | (1 << offset))
-                            })
-                        };
+                        set_output_type(OTYPER { _0: () }.otyper(), $i, true);
 
                         $PXi {_mode: PhantomData }
                     }
@@ -564,22 +592,62 @@ macro_rules! gpio {
                     }
                 }
 
+                #[cfg(feature = "async")]
+                impl<MODE> embedded_hal_1::digital::ErrorType for $PXi<Input<MODE>> {
+                    type Error = Infallible;
+                }
+
+                #[cfg(feature = "async")]
+                impl<MODE> embedded_hal_async::digital::Wait for $PXi<Input<MODE>> {
+                    async fn wait_for_high(&mut self) -> Result<(), Self::Error> {
+                        // arm the line before sampling, so the edge can't be missed
+                        let edge = ExtiInput::new($i, $extigpionr, Edge::Rising);
+                        if self.is_low()? {
+                            edge.await;
+                        }
+                        Ok(())
+                    }
+
+                    async fn wait_for_low(&mut self) -> Result<(), Self::Error> {
+                        let edge = ExtiInput::new($i, $extigpionr, Edge::Falling);
+                        if self.is_high()? {
+                            edge.await;
+                        }
+                        Ok(())
+                    }
+
+                    async fn wait_for_rising_edge(&mut self) -> Result<(), Self::Error> {
+                        ExtiInput::new($i, $extigpionr, Edge::Rising).await;
+                        Ok(())
+                    }
+
+                    async fn wait_for_falling_edge(&mut self) -> Result<(), Self::Error> {
+                        ExtiInput::new($i, $extigpionr, Edge::Falling).await;
+                        Ok(())
+                    }
+
+                    async fn wait_for_any_edge(&mut self) -> Result<(), Self::Error> {
+                        ExtiInput::new($i, $extigpionr, Edge::Any).await;
+                        Ok(())
+                    }
+                }
+
                 impl<MODE> $PXi<MODE> {
                     impl_into_af! {
                         $PXi $AFR $i,
-                        (AF0, 0, into_af0);
-                        (AF1, 1, into_af1);
-                        (AF2, 2, into_af2);
-                        (AF3, 3, into_af3);
-                        (AF4, 4, into_af4);
-                        (AF5, 5, into_af5);
-                        (AF6, 6, into_af6);
-                        (AF7, 7, into_af7);
-                        (AF8, 8, into_af8);
-                        (AF12, 12, into_af12);
-                        (AF13, 13, into_af13);
-                        (AF14, 14, into_af14);
-                        (AF15, 15, into_af15);
+                        (AF0, 0, into_af0, into_af0_atomic);
+                        (AF1, 1, into_af1, into_af1_atomic);
+                        (AF2, 2, into_af2, into_af2_atomic);
+                        (AF3, 3, into_af3, into_af3_atomic);
+                        (AF4, 4, into_af4, into_af4_atomic);
+                        (AF5, 5, into_af5, into_af5_atomic);
+                        (AF6, 6, into_af6, into_af6_atomic);
+                        (AF7, 7, into_af7, into_af7_atomic);
+                        (AF8, 8, into_af8, into_af8_atomic);
+                        (AF12, 12, into_af12, into_af12_atomic);
+                        (AF13, 13, into_af13, into_af13_atomic);
+                        (AF14, 14, into_af14, into_af14_atomic);
+                        (AF15, 15, into_af15, into_af15_atomic);
                     }
                 }
             )+
@@ -610,7 +678,7 @@ gpio!(GPIOA, gpioa, gpioa, gpioaen, gpioarst, PAx, 0, [
     PA15: (pa15, 15, Output<PushPull>, AFRH, exticr4),
 ]);
 
-gpio!(GPIOB, gpiob, gpiob, gpioben, gpiobrst, PAx, 0, [
+gpio!(GPIOB, gpiob, gpiob, gpioben, gpiobrst, PAx, 1, [
     PB0: (pa0, 0, Input<Analog>, AFRL, exticr1),
     PB1: (pa1, 1, Input<Analog>, AFRL, exticr1),
     PB2: (pa2, 2, Input<Analog>, AFRL, exticr1),
@@ -628,3 +696,16 @@ gpio!(GPIOB, gpiob, gpiob, gpioben, gpiobrst, PAx, 0, [
     PB14: (pa14, 14, Input<Analog>, AFRH, exticr4),
     PB15: (pa15, 15, Input<Analog>, AFRH, exticr4),
 ]);
+
+gpio!(GPIOC, gpioc, gpioc, gpiocen, gpiocrst, PCx, 2, [
+    PC0: (pc0, 0, Input<Analog>, AFRL, exticr1),
+    PC1: (pc1, 1, Input<Analog>, AFRL, exticr1),
+    PC2: (pc2, 2, Input<Analog>, AFRL, exticr1),
+    PC3: (pc3, 3, Input<Analog>, AFRL, exticr1),
+    PC4: (pc4, 4, Input<Analog>, AFRL, exticr2),
+    PC5: (pc5, 5, Input<Analog>, AFRL, exticr2),
+    PC6: (pc6, 6, Input<Analog>, AFRL, exticr2),
+    PC13: (pc13, 13, Input<Analog>, AFRH, exticr4),
+    PC14: (pc14, 14, Input<Analog>, AFRH, exticr4),
+    PC15: (pc15, 15, Input<Analog>, AFRH, exticr4),
+]);
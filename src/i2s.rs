@@ -0,0 +1,380 @@
+//! Inter-IC Sound (I2S) interface
+//!
+//! SPI2 can operate as an I2S controller. The CK, WS and SD signals are on the SCK, NSS and MOSI
+//! pins of SPI2, the master clock output MCK is optional. Samples are exchanged as 16-bit
+//! half-words, 24 and 32-bit samples take two of them, most significant half-word first.
+
+use core::ptr;
+
+use crate::gpio::{Alternate, Floating, Input, AF5, PA3, PC6};
+use crate::hal::spi::Polarity;
+use crate::pac::SPI2;
+use crate::rcc::{APB1R1, CCIPR};
+use crate::spi::{MosiPin, NssPin, SckPin};
+use crate::time::Hertz;
+
+/// I2S error
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum Error {
+    /// Overrun occurred
+    Overrun,
+    /// Underrun occurred
+    Underrun,
+    /// Frame format error, WS changed at an unexpected moment in slave mode
+    FrameFormat,
+    /// The sample rate cannot be derived from the I2S clock
+    InvalidSampleRate,
+}
+
+/// Role and direction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Slave transmitter
+    SlaveTx,
+    /// Slave receiver
+    SlaveRx,
+    /// Master transmitter, driving CK and WS
+    MasterTx,
+    /// Master receiver, driving CK and WS
+    MasterRx,
+}
+
+/// Audio standard
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Standard {
+    /// I2S Philips standard
+    Philips,
+    /// MSB justified (left justified) standard
+    Msb,
+    /// LSB justified (right justified) standard
+    Lsb,
+    /// PCM standard with a one clock period frame synchronization pulse
+    PcmShort,
+    /// PCM standard with a 13 clock periods frame synchronization pulse
+    PcmLong,
+}
+
+/// Data and channel length
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataFormat {
+    /// 16-bit data in a 16-bit channel
+    Data16Channel16,
+    /// 16-bit data in a 32-bit channel
+    Data16Channel32,
+    /// 24-bit data in a 32-bit channel
+    Data24Channel32,
+    /// 32-bit data in a 32-bit channel
+    Data32Channel32,
+}
+
+impl DataFormat {
+    fn channel_len_32(self) -> bool {
+        self != DataFormat::Data16Channel16
+    }
+}
+
+/// I2S kernel clock
+#[derive(Clone, Copy, Debug)]
+pub enum I2sClock {
+    /// PLL Q output running at the given frequency
+    PllQ(Hertz),
+    /// High-speed internal clock
+    Hsi16,
+    /// External clock on the I2S_CKIN pin running at the given frequency
+    External(Hertz),
+}
+
+impl I2sClock {
+    fn frequency(self) -> Hertz {
+        match self {
+            I2sClock::PllQ(freq) | I2sClock::External(freq) => freq,
+            I2sClock::Hsi16 => Hertz(16_000_000),
+        }
+    }
+
+    fn spi2s2sel(self) -> u8 {
+        match self {
+            I2sClock::PllQ(_) => 0b01,
+            I2sClock::Hsi16 => 0b10,
+            I2sClock::External(_) => 0b11,
+        }
+    }
+}
+
+/// I2S configuration
+#[derive(Clone, Copy)]
+pub struct Config {
+    /// Role and direction
+    pub mode: Mode,
+    /// Audio standard
+    pub standard: Standard,
+    /// Data and channel length
+    pub format: DataFormat,
+    /// Idle level of CK
+    pub polarity: Polarity,
+    /// Sample rate, only used in master mode
+    pub sample_rate: Hertz,
+}
+
+/// Computes the linear prescaler `I2SDIV` and `ODD` for the requested sample rate
+///
+/// The sample rate is `clock / (256 * (2 * I2SDIV + ODD))` with the master clock output enabled,
+/// otherwise `clock / (2 * channel length * (2 * I2SDIV + ODD))`. The divider closest to the
+/// requested sample rate is picked, `None` is returned if it is out of range.
+pub fn prescaler(
+    clock: Hertz,
+    sample_rate: Hertz,
+    master_clock: bool,
+    channel_len_32: bool,
+) -> Option<(u8, bool)> {
+    let frame_clocks: u32 = if master_clock {
+        256
+    } else if channel_len_32 {
+        64
+    } else {
+        32
+    };
+    let bit_clock = (sample_rate.0 as u64) * (frame_clocks as u64);
+    if bit_clock == 0 {
+        return None;
+    }
+
+    let div = (clock.0 as u64 + bit_clock / 2) / bit_clock;
+    // I2SDIV values 0 and 1 are forbidden
+    if !(4..=511).contains(&div) {
+        return None;
+    }
+    Some(((div / 2) as u8, div % 2 == 1))
+}
+
+/// Master clock (MCK) pin. This trait is sealed and cannot be implemented.
+pub trait MckPin<SPI>: private::Sealed {}
+
+#[doc(hidden)]
+mod private {
+    pub trait Sealed {}
+}
+
+impl private::Sealed for PA3<Alternate<AF5, Input<Floating>>> {}
+impl MckPin<SPI2> for PA3<Alternate<AF5, Input<Floating>>> {}
+impl private::Sealed for PC6<Alternate<AF5, Input<Floating>>> {}
+impl MckPin<SPI2> for PC6<Alternate<AF5, Input<Floating>>> {}
+
+/// I2S pins: (CK, WS, SD) or (CK, WS, SD, MCK). This trait is sealed and cannot be implemented.
+pub trait Pins<SPI>: private::Sealed {
+    #[doc(hidden)]
+    const MCK: bool;
+}
+
+impl<CK, WS, SD> private::Sealed for (CK, WS, SD) {}
+impl<CK, WS, SD> Pins<SPI2> for (CK, WS, SD)
+where
+    CK: SckPin<SPI2>,
+    WS: NssPin<SPI2>,
+    SD: MosiPin<SPI2>,
+{
+    const MCK: bool = false;
+}
+
+impl<CK, WS, SD, MCK> private::Sealed for (CK, WS, SD, MCK) {}
+impl<CK, WS, SD, MCK> Pins<SPI2> for (CK, WS, SD, MCK)
+where
+    CK: SckPin<SPI2>,
+    WS: NssPin<SPI2>,
+    SD: MosiPin<SPI2>,
+    MCK: MckPin<SPI2>,
+{
+    const MCK: bool = true;
+}
+
+/// I2S interface
+pub struct I2s<SPI, PINS> {
+    spi: SPI,
+    pins: PINS,
+}
+
+impl<PINS> I2s<SPI2, PINS>
+where
+    PINS: Pins<SPI2>,
+{
+    /// Configures SPI2 as I2S interface
+    ///
+    /// The interface is left disabled, see [`enable`](Self::enable). In master mode the
+    /// prescaler is derived from `clock`, the master clock is output if an MCK pin is passed in.
+    pub fn new(
+        spi: SPI2,
+        pins: PINS,
+        config: Config,
+        clock: I2sClock,
+        apb: &mut APB1R1,
+        ccipr: &mut CCIPR,
+    ) -> Result<Self, Error> {
+        let master = matches!(config.mode, Mode::MasterTx | Mode::MasterRx);
+        let (div, odd) = if master {
+            prescaler(
+                clock.frequency(),
+                config.sample_rate,
+                PINS::MCK,
+                config.format.channel_len_32(),
+            )
+            .ok_or(Error::InvalidSampleRate)?
+        } else {
+            // the prescaler is not used in slave mode, this is its reset value
+            (2, false)
+        };
+
+        ccipr
+            .ccipr()
+            .modify(|_, w| unsafe { w.spi2s2sel().bits(clock.spi2s2sel()) });
+
+        // enable or reset SPI2
+        apb.enr().modify(|_, w| w.spi2s2en().set_bit());
+        apb.rstr().modify(|_, w| w.spi2s2rst().set_bit());
+        apb.rstr().modify(|_, w| w.spi2s2rst().clear_bit());
+
+        // I2SDIV, ODD: linear prescaler
+        // MCKOE: master clock output enabled if there is an MCK pin
+        spi.i2spr.write(|w| unsafe {
+            w.i2sdiv()
+                .bits(div)
+                .odd()
+                .bit(odd)
+                .mckoe()
+                .bit(master && PINS::MCK)
+        });
+
+        let (datlen, chlen) = match config.format {
+            DataFormat::Data16Channel16 => (0b00, false),
+            DataFormat::Data16Channel32 => (0b00, true),
+            DataFormat::Data24Channel32 => (0b01, true),
+            DataFormat::Data32Channel32 => (0b10, true),
+        };
+        let (i2sstd, pcmsync) = match config.standard {
+            Standard::Philips => (0b00, false),
+            Standard::Msb => (0b01, false),
+            Standard::Lsb => (0b10, false),
+            Standard::PcmShort => (0b11, false),
+            Standard::PcmLong => (0b11, true),
+        };
+        let i2scfg = match config.mode {
+            Mode::SlaveTx => 0b00,
+            Mode::SlaveRx => 0b01,
+            Mode::MasterTx => 0b10,
+            Mode::MasterRx => 0b11,
+        };
+
+        // I2SMOD: I2S mode selected
+        // I2SE: I2S disabled
+        spi.i2scfgr.write(|w| unsafe {
+            w.i2smod()
+                .set_bit()
+                .i2scfg()
+                .bits(i2scfg)
+                .i2sstd()
+                .bits(i2sstd)
+                .pcmsync()
+                .bit(pcmsync)
+                .ckpol()
+                .bit(config.polarity == Polarity::IdleHigh)
+                .datlen()
+                .bits(datlen)
+                .chlen()
+                .bit(chlen)
+                .i2se()
+                .clear_bit()
+        });
+
+        Ok(I2s { spi, pins })
+    }
+
+    /// Enables the interface, in master mode this starts the clocks
+    pub fn enable(&mut self) {
+        self.spi.i2scfgr.modify(|_, w| w.i2se().set_bit());
+    }
+
+    /// Disables the interface
+    pub fn disable(&mut self) {
+        self.spi.i2scfgr.modify(|_, w| w.i2se().clear_bit());
+    }
+
+    /// Returns `true` if the next half-word to transmit or the last one received belongs to the
+    /// right channel
+    pub fn is_right_channel(&self) -> bool {
+        self.spi.sr.read().chside().bit_is_set()
+    }
+
+    /// Reads a received half-word
+    pub fn read(&mut self) -> nb::Result<u16, Error> {
+        let sr = self.spi.sr.read();
+
+        Err(if sr.ovr().bit_is_set() {
+            nb::Error::Other(Error::Overrun)
+        } else if sr.tifrfe().bit_is_set() {
+            nb::Error::Other(Error::FrameFormat)
+        } else if sr.rxne().bit_is_set() {
+            // NOTE(read_volatile) read a half-word (the svd2rust API only allows reading a word)
+            return Ok(unsafe { ptr::read_volatile(ptr::addr_of!(self.spi.dr) as *const u16) });
+        } else {
+            nb::Error::WouldBlock
+        })
+    }
+
+    /// Writes a half-word to transmit
+    pub fn send(&mut self, half_word: u16) -> nb::Result<(), Error> {
+        let sr = self.spi.sr.read();
+
+        Err(if sr.udr().bit_is_set() {
+            nb::Error::Other(Error::Underrun)
+        } else if sr.tifrfe().bit_is_set() {
+            nb::Error::Other(Error::FrameFormat)
+        } else if sr.txe().bit_is_set() {
+            self.spi.dr.write(|w| unsafe { w.dr().bits(half_word) });
+            return Ok(());
+        } else {
+            nb::Error::WouldBlock
+        })
+    }
+
+    /// Disables the interface and releases the SPI peripheral and associated pins
+    pub fn free(mut self) -> (SPI2, PINS) {
+        self.disable();
+        (self.spi, self.pins)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::prescaler;
+    use crate::time::Hertz;
+
+    #[test]
+    fn odd_divider_is_rounded() {
+        // 16 MHz / (8 kHz * 32) = 62.5
+        assert_eq!(
+            prescaler(Hertz(16_000_000), Hertz(8_000), false, false),
+            Some((31, true))
+        );
+    }
+
+    #[test]
+    fn master_clock_divides_by_256() {
+        // 16 MHz / (8 kHz * 256) = 7.8
+        assert_eq!(
+            prescaler(Hertz(16_000_000), Hertz(8_000), true, false),
+            Some((4, false))
+        );
+        // 32-bit channels without master clock: 16 MHz / (8 kHz * 64) = 31.25
+        assert_eq!(
+            prescaler(Hertz(16_000_000), Hertz(8_000), false, true),
+            Some((15, true))
+        );
+    }
+
+    #[test]
+    fn out_of_range_is_rejected() {
+        assert_eq!(prescaler(Hertz(12_288_000), Hertz(48_000), true, false), None);
+        assert_eq!(prescaler(Hertz(16_000_000), Hertz(100), false, false), None);
+        assert_eq!(prescaler(Hertz(16_000_000), Hertz(0), false, false), None);
+    }
+}
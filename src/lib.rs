@@ -30,15 +30,27 @@ pub use crate::pac as device;
 #[doc(hidden)]
 pub use crate::pac as stm32;
 
+#[cfg(feature = "device-selected")]
+pub mod debounce;
+#[cfg(feature = "device-selected")]
+pub mod dma;
+#[cfg(all(feature = "device-selected", feature = "async"))]
+pub mod exti;
 // #[cfg(feature = "device-selected")]
 // pub mod flash;
 #[cfg(feature = "device-selected")]
 pub mod gpio;
 #[cfg(feature = "device-selected")]
+pub mod i2s;
+#[cfg(feature = "device-selected")]
 pub mod prelude;
 #[cfg(feature = "device-selected")]
 pub mod rcc;
 #[cfg(feature = "device-selected")]
+pub mod rfswitch;
+#[cfg(feature = "device-selected")]
 pub mod spi;
 #[cfg(feature = "device-selected")]
+pub mod subghz;
+#[cfg(feature = "device-selected")]
 pub mod time;
@@ -12,17 +12,47 @@ pub trait RccExt {
 impl RccExt for RCC {
     fn constrain(self) -> Rcc {
         Rcc {
+            ahb1: AHB1 { _0: () },
             ahb2: AHB2 { _0: () },
+            apb1r1: APB1R1 { _0: () },
+            apb2: APB2 { _0: () },
             apb3: APB3 { _0: () },
+            ccipr: CCIPR { _0: () },
         }
     }
 }
 
 /// Constrained RCC peripheral
 pub struct Rcc {
+    /// AMBA High-performance Bus (AHB1) registers
+    pub ahb1: AHB1,
     /// AMBA High-performance Bus (AHB2) registers
     pub ahb2: AHB2,
+    /// Advanced Peripheral Bus 1 (APB1) registers, first half
+    pub apb1r1: APB1R1,
+    /// Advanced Peripheral Bus 2 (APB2) registers
+    pub apb2: APB2,
+    /// Advanced Peripheral Bus 3 (APB3) registers
     pub apb3: APB3,
+    /// Peripherals independent clock configuration register
+    pub ccipr: CCIPR,
+}
+
+/// AMBA High-performance Bus 1 (AHB1) registers
+pub struct AHB1 {
+    _0: (),
+}
+
+impl AHB1 {
+    pub(crate) fn enr(&mut self) -> &rcc::AHB1ENR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*RCC::ptr()).ahb1enr }
+    }
+
+    pub(crate) fn rstr(&mut self) -> &rcc::AHB1RSTR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*RCC::ptr()).ahb1rstr }
+    }
 }
 
 /// AMBA High-performance Bus 2 (AHB2) registers
@@ -42,6 +72,40 @@ impl AHB2 {
     }
 }
 
+/// Advanced Peripheral Bus 1 (APB1) registers, first half
+pub struct APB1R1 {
+    _0: (),
+}
+
+impl APB1R1 {
+    pub(crate) fn enr(&mut self) -> &rcc::APB1ENR1 {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*RCC::ptr()).apb1enr1 }
+    }
+
+    pub(crate) fn rstr(&mut self) -> &rcc::APB1RSTR1 {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*RCC::ptr()).apb1rstr1 }
+    }
+}
+
+/// Advanced Peripheral Bus 2 (APB2) registers
+pub struct APB2 {
+    _0: (),
+}
+
+impl APB2 {
+    pub(crate) fn enr(&mut self) -> &rcc::APB2ENR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*RCC::ptr()).apb2enr }
+    }
+
+    pub(crate) fn rstr(&mut self) -> &rcc::APB2RSTR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*RCC::ptr()).apb2rstr }
+    }
+}
+
 /// Advanced Peripheral Bus 3 (APB3) registers
 pub struct APB3 {
     _0: (),
@@ -59,6 +123,18 @@ impl APB3 {
     }
 }
 
+/// Peripherals independent clock configuration register
+pub struct CCIPR {
+    _0: (),
+}
+
+impl CCIPR {
+    pub(crate) fn ccipr(&mut self) -> &rcc::CCIPR {
+        // NOTE(unsafe) this proxy grants exclusive access to this register
+        unsafe { &(*RCC::ptr()).ccipr }
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum MsiFreq {
     #[doc = "range 0 around 100 kHz"]
@@ -139,6 +215,7 @@ pub struct Clocks {
     lse: bool,
     pclk1: Hertz,
     pclk2: Hertz,
+    pclk3: Hertz,
     ppre1: u8,
     ppre2: u8,
     sysclk: Hertz,
@@ -176,6 +253,11 @@ impl Clocks {
         self.pclk2
     }
 
+    /// Returns the frequency of the APB3, equal to the shared AHB3 clock HCLK3
+    pub fn pclk3(&self) -> Hertz {
+        self.pclk3
+    }
+
     // TODO remove `allow`
     #[allow(dead_code)]
     pub(crate) fn ppre1(&self) -> u8 {
@@ -0,0 +1,216 @@
+//! RF switch control
+//!
+//! The sub-GHz radio has separate receive, low power transmit and high power transmit paths.
+//! Boards route these to the antenna through an external RF switch, driven by two or three
+//! GPIOs. The [`RfSwitch`] trait abstracts over this, so radio code can select a path without
+//! knowing how the board is wired.
+
+use crate::hal::digital::v2::OutputPin;
+
+/// RF switch path
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RfState {
+    /// All paths disconnected
+    Off,
+    /// Antenna connected to the receive path
+    Rx,
+    /// Antenna connected to the low power amplifier
+    TxLowPower,
+    /// Antenna connected to the high power amplifier
+    TxHighPower,
+}
+
+/// RF switch error
+#[derive(Debug)]
+pub enum Error<E> {
+    /// Driving one of the control pins failed
+    Pin(E),
+    /// The requested path is not available on this board
+    Unsupported,
+}
+
+/// An RF switch that connects the antenna to one of the radio paths
+pub trait RfSwitch {
+    /// Error type
+    type Error;
+
+    /// Connects the antenna to the given path
+    fn set_state(&mut self, state: RfState) -> Result<(), Self::Error>;
+
+    /// Disconnects all paths
+    fn set_off(&mut self) -> Result<(), Self::Error> {
+        self.set_state(RfState::Off)
+    }
+
+    /// Connects the antenna to the receive path
+    fn set_rx(&mut self) -> Result<(), Self::Error> {
+        self.set_state(RfState::Rx)
+    }
+
+    /// Connects the antenna to the low power amplifier
+    fn set_tx_low_power(&mut self) -> Result<(), Self::Error> {
+        self.set_state(RfState::TxLowPower)
+    }
+
+    /// Connects the antenna to the high power amplifier
+    fn set_tx_high_power(&mut self) -> Result<(), Self::Error> {
+        self.set_state(RfState::TxHighPower)
+    }
+}
+
+/// Control pin levels for each switch path
+///
+/// `true` means the pin is driven high. Paths that are `None` are not wired up on the board and
+/// are rejected with [`Error::Unsupported`].
+#[derive(Clone, Copy, Debug)]
+pub struct Levels<L> {
+    /// Levels for [`RfState::Off`]
+    pub off: Option<L>,
+    /// Levels for [`RfState::Rx`]
+    pub rx: Option<L>,
+    /// Levels for [`RfState::TxLowPower`]
+    pub tx_low_power: Option<L>,
+    /// Levels for [`RfState::TxHighPower`]
+    pub tx_high_power: Option<L>,
+}
+
+impl<L: Copy> Levels<L> {
+    fn get(&self, state: RfState) -> Option<L> {
+        match state {
+            RfState::Off => self.off,
+            RfState::Rx => self.rx,
+            RfState::TxLowPower => self.tx_low_power,
+            RfState::TxHighPower => self.tx_high_power,
+        }
+    }
+}
+
+fn set_level<P, E>(pin: &mut P, high: bool) -> Result<(), Error<E>>
+where
+    P: OutputPin<Error = E>,
+{
+    if high {
+        pin.set_high().map_err(Error::Pin)
+    } else {
+        pin.set_low().map_err(Error::Pin)
+    }
+}
+
+/// RF switch driven by two control pins
+pub struct TwoPinSwitch<CTRL1, CTRL2> {
+    ctrl1: CTRL1,
+    ctrl2: CTRL2,
+    levels: Levels<[bool; 2]>,
+}
+
+impl<CTRL1, CTRL2, E> TwoPinSwitch<CTRL1, CTRL2>
+where
+    CTRL1: OutputPin<Error = E>,
+    CTRL2: OutputPin<Error = E>,
+{
+    /// Creates a switch with a board specific truth table
+    pub fn new(ctrl1: CTRL1, ctrl2: CTRL2, levels: Levels<[bool; 2]>) -> Self {
+        TwoPinSwitch {
+            ctrl1,
+            ctrl2,
+            levels,
+        }
+    }
+
+    /// Creates the switch of the Seeed LoRa-E5 module, with `ctrl1` on PA4 and `ctrl2` on PA5
+    ///
+    /// Only the high power amplifier is connected on this module.
+    pub fn lora_e5(ctrl1: CTRL1, ctrl2: CTRL2) -> Self {
+        Self::new(
+            ctrl1,
+            ctrl2,
+            Levels {
+                off: Some([false, false]),
+                rx: Some([true, false]),
+                tx_low_power: None,
+                tx_high_power: Some([false, true]),
+            },
+        )
+    }
+
+    /// Releases the control pins
+    pub fn free(self) -> (CTRL1, CTRL2) {
+        (self.ctrl1, self.ctrl2)
+    }
+}
+
+impl<CTRL1, CTRL2, E> RfSwitch for TwoPinSwitch<CTRL1, CTRL2>
+where
+    CTRL1: OutputPin<Error = E>,
+    CTRL2: OutputPin<Error = E>,
+{
+    type Error = Error<E>;
+
+    fn set_state(&mut self, state: RfState) -> Result<(), Self::Error> {
+        let [l1, l2] = self.levels.get(state).ok_or(Error::Unsupported)?;
+        set_level(&mut self.ctrl1, l1)?;
+        set_level(&mut self.ctrl2, l2)
+    }
+}
+
+/// RF switch driven by three control pins
+pub struct ThreePinSwitch<CTRL1, CTRL2, CTRL3> {
+    ctrl1: CTRL1,
+    ctrl2: CTRL2,
+    ctrl3: CTRL3,
+    levels: Levels<[bool; 3]>,
+}
+
+impl<CTRL1, CTRL2, CTRL3, E> ThreePinSwitch<CTRL1, CTRL2, CTRL3>
+where
+    CTRL1: OutputPin<Error = E>,
+    CTRL2: OutputPin<Error = E>,
+    CTRL3: OutputPin<Error = E>,
+{
+    /// Creates a switch with a board specific truth table
+    pub fn new(ctrl1: CTRL1, ctrl2: CTRL2, ctrl3: CTRL3, levels: Levels<[bool; 3]>) -> Self {
+        ThreePinSwitch {
+            ctrl1,
+            ctrl2,
+            ctrl3,
+            levels,
+        }
+    }
+
+    /// Creates the switch of the NUCLEO-WL55JC boards, with `ctrl1`, `ctrl2` and `ctrl3` on the
+    /// FE_CTRL1 (PC4), FE_CTRL2 (PC5) and FE_CTRL3 (PC3) pins
+    pub fn nucleo(ctrl1: CTRL1, ctrl2: CTRL2, ctrl3: CTRL3) -> Self {
+        Self::new(
+            ctrl1,
+            ctrl2,
+            ctrl3,
+            Levels {
+                off: Some([false, false, false]),
+                rx: Some([true, false, true]),
+                tx_low_power: Some([true, true, true]),
+                tx_high_power: Some([false, true, true]),
+            },
+        )
+    }
+
+    /// Releases the control pins
+    pub fn free(self) -> (CTRL1, CTRL2, CTRL3) {
+        (self.ctrl1, self.ctrl2, self.ctrl3)
+    }
+}
+
+impl<CTRL1, CTRL2, CTRL3, E> RfSwitch for ThreePinSwitch<CTRL1, CTRL2, CTRL3>
+where
+    CTRL1: OutputPin<Error = E>,
+    CTRL2: OutputPin<Error = E>,
+    CTRL3: OutputPin<Error = E>,
+{
+    type Error = Error<E>;
+
+    fn set_state(&mut self, state: RfState) -> Result<(), Self::Error> {
+        let [l1, l2, l3] = self.levels.get(state).ok_or(Error::Unsupported)?;
+        set_level(&mut self.ctrl1, l1)?;
+        set_level(&mut self.ctrl2, l2)?;
+        set_level(&mut self.ctrl3, l3)
+    }
+}
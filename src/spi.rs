@@ -1,10 +1,21 @@
 //! Serial Peripheral Interface (SPI) bus
 
+use core::cell::RefCell;
 use core::ptr;
-use crate::hal::spi::{FullDuplex, Mode, Phase, Polarity};
+use core::sync::atomic::{self, Ordering};
+use crate::hal::blocking::spi::{Transfer, Write};
+use crate::hal::digital::v2::OutputPin;
+use crate::hal::spi::{FullDuplex, Mode, Phase, Polarity, MODE_0};
+use embedded_dma::{StaticReadBuffer, StaticWriteBuffer};
 
-use crate::gpio::{Alternate, Floating, Input, PA4, PA5, PA6, PA7, AF13};
-use crate::rcc::{Clocks, APB3};
+use crate::dma::{self, Direction, TransferPayload};
+
+use crate::gpio::{
+    Alternate, Floating, Input, AF13, AF3, AF5, PA1, PA10, PA11, PA12, PA15, PA4, PA5, PA6, PA7,
+    PA8, PA9, PB10, PB12, PB13, PB14, PB15, PB2, PB3, PB4, PB5, PB9, PC1, PC2, PC3,
+};
+use crate::pac::{SPI1, SPI2};
+use crate::rcc::{Clocks, APB1R1, APB2, APB3};
 use crate::time::Hertz;
 
 /// SPI error
@@ -17,11 +28,296 @@ pub enum Error {
     ModeFault,
     /// CRC error
     Crc,
+    /// Frame format error, NSS changed at an unexpected moment in TI mode
+    FrameFormat,
+    /// The configuration can't be changed while a transfer is ongoing
+    Busy,
+    /// The word type doesn't match the data frame size: frames of up to 8 bits are exchanged as
+    /// `u8`, larger ones as `u16`
+    WordSize,
+    /// The requested SCK frequency is above the peripheral clock divided by 2 or the maximum of
+    /// the peripheral
+    FrequencyTooHigh,
+    /// The requested SCK frequency is below the peripheral clock divided by 256
+    FrequencyTooLow,
+    /// NSS pulses were requested in a mode that captures data on the second clock transition
+    NssPulsePhase,
+}
+
+/// Maximum SCK frequency of the sub-GHz radio SPI
+pub const SUBGHZSPI_MAX_FREQ: Hertz = Hertz(16_000_000);
+
+/// Picks the baud rate prescaler for the requested SCK frequency
+///
+/// The peripheral clock `pclk` is divided by 2, 4, ..., 256. The smallest divider that doesn't
+/// exceed `freq` is picked, returned as `BR` value along with the resulting SCK frequency.
+/// Requests above `pclk / 2` or `max`, or below `pclk / 256`, are rejected.
+pub fn prescaler(pclk: Hertz, freq: Hertz, max: Option<Hertz>) -> Result<(u8, Hertz), Error> {
+    if freq.0 > pclk.0 / 2 || max.map_or(false, |max| freq.0 > max.0) {
+        return Err(Error::FrequencyTooHigh);
+    }
+    if freq.0 == 0 {
+        return Err(Error::FrequencyTooLow);
+    }
+
+    let ratio = (pclk.0 + freq.0 - 1) / freq.0;
+    // BR = n divides by 2^(n + 1)
+    let br = (0..8u8)
+        .find(|br| 2u32 << br >= ratio)
+        .ok_or(Error::FrequencyTooLow)?;
+    Ok((br, Hertz(pclk.0 >> (br + 1))))
+}
+
+#[cfg(feature = "eh1")]
+impl embedded_hal_1::spi::Error for Error {
+    fn kind(&self) -> embedded_hal_1::spi::ErrorKind {
+        use embedded_hal_1::spi::ErrorKind;
+
+        match self {
+            Error::Overrun => ErrorKind::Overrun,
+            Error::ModeFault => ErrorKind::ModeFault,
+            Error::FrameFormat => ErrorKind::FrameFormat,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+/// Fill level of the RX or TX FIFO
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FifoLevel {
+    /// FIFO empty
+    Empty,
+    /// 1/4 FIFO
+    Quarter,
+    /// 1/2 FIFO
+    Half,
+    /// FIFO full
+    Full,
+}
+
+impl FifoLevel {
+    fn from_bits(bits: u8) -> Self {
+        match bits {
+            0b00 => FifoLevel::Empty,
+            0b01 => FifoLevel::Quarter,
+            0b10 => FifoLevel::Half,
+            _ => FifoLevel::Full,
+        }
+    }
+}
+
+/// Data frame size
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DataSize {
+    /// 4-bit frames
+    Bits4,
+    /// 5-bit frames
+    Bits5,
+    /// 6-bit frames
+    Bits6,
+    /// 7-bit frames
+    Bits7,
+    /// 8-bit frames
+    Bits8,
+    /// 9-bit frames
+    Bits9,
+    /// 10-bit frames
+    Bits10,
+    /// 11-bit frames
+    Bits11,
+    /// 12-bit frames
+    Bits12,
+    /// 13-bit frames
+    Bits13,
+    /// 14-bit frames
+    Bits14,
+    /// 15-bit frames
+    Bits15,
+    /// 16-bit frames
+    Bits16,
+}
+
+impl DataSize {
+    /// Returns the number of bits in a frame
+    pub fn bits(self) -> u8 {
+        self as u8 + 4
+    }
+}
+
+/// Length of the hardware calculated CRC
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrcLength {
+    /// 8-bit CRC
+    Bits8,
+    /// 16-bit CRC
+    Bits16,
+}
+
+/// Behaviour of the hardware NSS output in master mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NssMode {
+    /// NSS is driven low as long as the SPI is enabled
+    Static,
+    /// NSS is pulsed high between frames, only supported with
+    /// `Phase::CaptureOnFirstTransition`
+    Pulse,
+}
+
+/// Order in which the bits of a frame are shifted out
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BitOrder {
+    /// Most significant bit first
+    MsbFirst,
+    /// Least significant bit first
+    LsbFirst,
+}
+
+/// Full duplex master mode (type state)
+pub struct Master {
+    sck: Hertz,
+}
+
+/// Half duplex (3-wire) master mode (type state)
+pub struct HalfDuplex {
+    sck: Hertz,
+}
+
+/// Full duplex slave mode (type state)
+pub struct Slave {
+    /// Number of frames written that the master did not clock out yet
+    pending: u32,
+    underrun: bool,
 }
 
 #[doc(hidden)]
 mod private {
+    use core::ptr;
+
+    use crate::time::Hertz;
+
     pub trait Sealed {}
+
+    /// Operating mode of an SPI peripheral, tracking the frames exchanged in its mode
+    pub trait Role {
+        /// The peripheral drives SCK
+        const MASTER: bool;
+
+        /// A frame was written to the TX FIFO
+        fn sent(&mut self) {}
+
+        /// A frame was read from the RX FIFO
+        fn received(&mut self) {}
+
+        /// A DMA transfer stopped, the frames it exchanged were not tracked
+        fn dma_stopped(&mut self) {}
+    }
+
+    /// Master mode, driving SCK at a configurable frequency
+    pub trait MasterRole: Role {
+        fn sck(&self) -> Hertz;
+
+        fn sck_mut(&mut self) -> &mut Hertz;
+    }
+
+    /// Full duplex mode, exchanging a frame for every frame sent
+    pub trait FullDuplexRole: Role {}
+
+    impl Role for super::Master {
+        const MASTER: bool = true;
+    }
+
+    impl MasterRole for super::Master {
+        fn sck(&self) -> Hertz {
+            self.sck
+        }
+
+        fn sck_mut(&mut self) -> &mut Hertz {
+            &mut self.sck
+        }
+    }
+
+    impl FullDuplexRole for super::Master {}
+
+    impl Role for super::HalfDuplex {
+        const MASTER: bool = true;
+    }
+
+    impl MasterRole for super::HalfDuplex {
+        fn sck(&self) -> Hertz {
+            self.sck
+        }
+
+        fn sck_mut(&mut self) -> &mut Hertz {
+            &mut self.sck
+        }
+    }
+
+    impl Role for super::Slave {
+        const MASTER: bool = false;
+
+        fn sent(&mut self) {
+            self.pending += 1;
+        }
+
+        fn received(&mut self) {
+            // every received frame clocked out a written one, if there was none the master
+            // received stale data
+            match self.pending.checked_sub(1) {
+                Some(pending) => self.pending = pending,
+                None => self.underrun = true,
+            }
+        }
+
+        fn dma_stopped(&mut self) {
+            self.pending = 0;
+        }
+    }
+
+    impl FullDuplexRole for super::Slave {}
+
+    /// A data frame, read from and written to DR with an access of its own width
+    pub trait Word: Copy + Default {
+        /// Returns `true` if frames of `frame_bits` bits are exchanged as this type
+        fn fits(frame_bits: u8) -> bool;
+
+        /// # Safety
+        ///
+        /// `dr` must point to the data register of an SPI peripheral
+        unsafe fn read(dr: *const u32) -> Self;
+
+        /// # Safety
+        ///
+        /// `dr` must point to the data register of an SPI peripheral
+        unsafe fn write(dr: *const u32, word: Self);
+    }
+
+    impl Word for u8 {
+        fn fits(frame_bits: u8) -> bool {
+            frame_bits <= 8
+        }
+
+        unsafe fn read(dr: *const u32) -> Self {
+            ptr::read_volatile(dr as *const u8)
+        }
+
+        unsafe fn write(dr: *const u32, word: Self) {
+            ptr::write_volatile(dr as *mut u8, word)
+        }
+    }
+
+    impl Word for u16 {
+        fn fits(frame_bits: u8) -> bool {
+            frame_bits > 8
+        }
+
+        unsafe fn read(dr: *const u32) -> Self {
+            ptr::read_volatile(dr as *const u16)
+        }
+
+        unsafe fn write(dr: *const u32, word: Self) {
+            ptr::write_volatile(dr as *mut u16, word)
+        }
+    }
 }
 
 /// SCK pin. This trait is sealed and cannot be implemented.
@@ -30,6 +326,8 @@ pub trait SckPin<SPI>: private::Sealed {}
 pub trait MisoPin<SPI>: private::Sealed {}
 /// MOSI pin. This trait is sealed and cannot be implemented.
 pub trait MosiPin<SPI>: private::Sealed {}
+/// NSS pin. This trait is sealed and cannot be implemented.
+pub trait NssPin<SPI>: private::Sealed {}
 
 macro_rules! pins {
     ($spi:ident,
@@ -52,41 +350,270 @@ macro_rules! pins {
         )*
         $(
             impl private::Sealed for $nssout<Alternate<$af, Input<Floating>>> {}
-            impl MosiPin<$spi> for $nssout<Alternate<$af, Input<Floating>>> {}
+            impl NssPin<$spi> for $nssout<Alternate<$af, Input<Floating>>> {}
         )*
     }
 }
 
-/// SPI peripheral operating in full duplex master mode
+/// SPI peripheral operating in full duplex master, half duplex master or slave mode, see
+/// [`Master`], [`HalfDuplex`] and [`Slave`]
 /// This code has not been tested, please use with care
-pub struct Spi<SPI, PINS> {
+pub struct Spi<SPI, PINS, ROLE = Master> {
     spi: SPI,
     pins: PINS,
+    role: ROLE,
+}
+
+impl<SPI, PINS> Spi<SPI, PINS, Slave> {
+    /// Returns `true` if the master clocked out a frame that was not written since the last
+    /// call, and clears the flag
+    ///
+    /// The master received stale data in place of such a frame. The frames read are not
+    /// affected.
+    pub fn take_underrun(&mut self) -> bool {
+        core::mem::replace(&mut self.role.underrun, false)
+    }
+}
+
+/// An SPI bus shared by several devices, each with its own chip select pin
+///
+/// The bus is not interrupt safe: a transfer on a device started while another transfer on the
+/// same bus is ongoing, e.g. from an interrupt handler, fails with [`DeviceError::BusBusy`].
+pub struct SharedBus<SPI> {
+    spi: RefCell<SPI>,
+}
+
+impl<SPI> SharedBus<SPI> {
+    /// Creates a shared bus from a master mode SPI peripheral
+    pub fn new(spi: SPI) -> Self {
+        SharedBus {
+            spi: RefCell::new(spi),
+        }
+    }
+
+    /// Creates a device on the bus that is selected by driving `cs` low
+    ///
+    /// `cs` should be high when it is passed in. The device only supports the embedded-hal 0.2
+    /// traits, see [`NoDelay`].
+    pub fn device<CS>(&self, cs: CS) -> SpiDevice<'_, SPI, CS> {
+        self.device_with_delay(cs, NoDelay)
+    }
+
+    /// Creates a device on the bus that is selected by driving `cs` low, using `delay` for the
+    /// delay operations of embedded-hal 1.0 transactions
+    ///
+    /// `cs` should be high when it is passed in.
+    pub fn device_with_delay<CS, D>(&self, cs: CS, delay: D) -> SpiDevice<'_, SPI, CS, D> {
+        SpiDevice {
+            bus: &self.spi,
+            cs,
+            delay,
+        }
+    }
+
+    /// Releases the SPI peripheral
+    pub fn free(self) -> SPI {
+        self.spi.into_inner()
+    }
+}
+
+/// SPI device error
+#[derive(Debug)]
+pub enum DeviceError<E, CSE> {
+    /// The transfer on the bus failed
+    Spi(E),
+    /// Driving the chip select pin failed
+    ChipSelect(CSE),
+    /// The bus is in use by another transfer
+    BusBusy,
+}
+
+#[cfg(feature = "eh1")]
+impl<E, CSE> embedded_hal_1::spi::Error for DeviceError<E, CSE>
+where
+    E: embedded_hal_1::spi::Error,
+    CSE: core::fmt::Debug,
+{
+    fn kind(&self) -> embedded_hal_1::spi::ErrorKind {
+        use embedded_hal_1::spi::ErrorKind;
+
+        match self {
+            DeviceError::Spi(e) => e.kind(),
+            DeviceError::ChipSelect(_) => ErrorKind::ChipSelectFault,
+            DeviceError::BusBusy => ErrorKind::Other,
+        }
+    }
+}
+
+/// Delay of a [`SpiDevice`] created without one
+///
+/// Such a device doesn't implement the embedded-hal 1.0 `SpiDevice` trait, whose transactions
+/// can contain delay operations. Create it with [`SharedBus::device_with_delay`] instead.
+pub struct NoDelay;
+
+/// A device on a [`SharedBus`]
+///
+/// The device is selected for the duration of each blocking transfer or write, or each
+/// embedded-hal 1.0 transaction, so drivers owning different devices on the same bus don't
+/// interfere.
+pub struct SpiDevice<'a, SPI, CS, D = NoDelay> {
+    bus: &'a RefCell<SPI>,
+    cs: CS,
+    delay: D,
+}
+
+impl<'a, SPI, CS, CSE, D> SpiDevice<'a, SPI, CS, D>
+where
+    CS: OutputPin<Error = CSE>,
+{
+    fn with_selected<R, E>(
+        &mut self,
+        f: impl FnOnce(&mut SPI, &mut D) -> Result<R, E>,
+    ) -> Result<R, DeviceError<E, CSE>> {
+        let mut bus = self
+            .bus
+            .try_borrow_mut()
+            .map_err(|_| DeviceError::BusBusy)?;
+        self.cs.set_low().map_err(DeviceError::ChipSelect)?;
+        let result = f(&mut bus, &mut self.delay);
+        // deselect even if the transfer failed
+        let deselect = self.cs.set_high();
+        let result = result.map_err(DeviceError::Spi)?;
+        deselect.map_err(DeviceError::ChipSelect)?;
+        Ok(result)
+    }
+
+    /// Releases the chip select pin and the delay
+    pub fn free(self) -> (CS, D) {
+        (self.cs, self.delay)
+    }
+}
+
+#[cfg(feature = "eh1")]
+impl<'a, SPI, CS, D> embedded_hal_1::spi::ErrorType for SpiDevice<'a, SPI, CS, D>
+where
+    SPI: embedded_hal_1::spi::ErrorType,
+    CS: OutputPin,
+    CS::Error: core::fmt::Debug,
+{
+    type Error = DeviceError<SPI::Error, CS::Error>;
+}
+
+#[cfg(feature = "eh1")]
+impl<'a, SPI, CS, D, W> embedded_hal_1::spi::SpiDevice<W> for SpiDevice<'a, SPI, CS, D>
+where
+    SPI: embedded_hal_1::spi::SpiBus<W>,
+    CS: OutputPin,
+    CS::Error: core::fmt::Debug,
+    D: embedded_hal_1::delay::DelayNs,
+    W: Copy + 'static,
+{
+    fn transaction(
+        &mut self,
+        operations: &mut [embedded_hal_1::spi::Operation<'_, W>],
+    ) -> Result<(), Self::Error> {
+        use embedded_hal_1::spi::Operation;
+
+        self.with_selected(|spi, delay| {
+            for operation in operations {
+                match operation {
+                    Operation::Read(words) => spi.read(words)?,
+                    Operation::Write(words) => spi.write(words)?,
+                    Operation::Transfer(read, write) => spi.transfer(read, write)?,
+                    Operation::TransferInPlace(words) => spi.transfer_in_place(words)?,
+                    Operation::DelayNs(ns) => {
+                        spi.flush()?;
+                        delay.delay_ns(*ns);
+                    }
+                }
+            }
+            spi.flush()
+        })
+    }
 }
 
+macro_rules! device {
+    ($($W:ty),+) => {
+        $(
+            impl<'a, SPI, CS, CSE, D> Transfer<$W> for SpiDevice<'a, SPI, CS, D>
+            where
+                SPI: Transfer<$W>,
+                CS: OutputPin<Error = CSE>,
+            {
+                type Error = DeviceError<SPI::Error, CSE>;
+
+                fn transfer<'w>(&mut self, words: &'w mut [$W]) -> Result<&'w [$W], Self::Error> {
+                    self.with_selected(move |spi, _| spi.transfer(words))
+                }
+            }
+
+            impl<'a, SPI, CS, CSE, D> Write<$W> for SpiDevice<'a, SPI, CS, D>
+            where
+                SPI: Write<$W>,
+                CS: OutputPin<Error = CSE>,
+            {
+                type Error = DeviceError<SPI::Error, CSE>;
+
+                fn write(&mut self, words: &[$W]) -> Result<(), Self::Error> {
+                    self.with_selected(|spi, _| spi.write(words))
+                }
+            }
+        )+
+    }
+}
+
+device!(u8, u16);
+
 macro_rules! hal {
-    ($($SPIX:ident: ($spiX:ident, $APBX:ident, $spiXen:ident, $spiXrst:ident, $pclkX:ident),)+) => {
+    ($($SPIX:ident: ($spiX:ident, $APBX:ident, $spiXen:ident, $spiXrst:ident, $pclkX:ident,
+                     $max:expr, $rxreq:expr, $txreq:expr),)+) => {
         $(
             impl<SCK, MISO, MOSI> Spi<$SPIX, (SCK, MISO, MOSI)> {
                 /// Configures the SPI peripheral to operate in full duplex master mode
+                ///
+                /// If `freq` can't be reached, the peripheral and pins are returned along with
+                /// the error.
                 pub fn $spiX<F>(
                     spi: $SPIX,
                     pins: (SCK, MISO, MOSI),
                     mode: Mode,
                     freq: F,
                     clocks: Clocks,
-                    apb3: &mut $APBX,
-                ) -> Self
+                    apb: &mut $APBX,
+                ) -> Result<Self, (Error, $SPIX, (SCK, MISO, MOSI))>
                 where
                     F: Into<Hertz>,
                     SCK: SckPin<$SPIX>,
                     MISO: MisoPin<$SPIX>,
                     MOSI: MosiPin<$SPIX>,
                 {
+                    Self::new_master(spi, pins, mode, freq.into(), clocks, apb)
+                }
+
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, (SCK, MISO, MOSI)) {
+                    (self.spi, self.pins)
+                }
+            }
+
+            impl<PINS> Spi<$SPIX, PINS> {
+                fn new_master(
+                    spi: $SPIX,
+                    pins: PINS,
+                    mode: Mode,
+                    freq: Hertz,
+                    clocks: Clocks,
+                    apb: &mut $APBX,
+                ) -> Result<Self, (Error, $SPIX, PINS)> {
+                    let (br, sck) = match prescaler(clocks.$pclkX(), freq, $max) {
+                        Ok(prescaler) => prescaler,
+                        Err(e) => return Err((e, spi, pins)),
+                    };
+
                     // enable or reset $SPIX
-                    apb3.enr().modify(|_, w| w.$spiXen().set_bit());
-                    apb3.rstr().modify(|_, w| w.$spiXrst().set_bit());
-                    apb3.rstr().modify(|_, w| w.$spiXrst().clear_bit());
+                    apb.enr().modify(|_, w| w.$spiXen().set_bit());
+                    apb.rstr().modify(|_, w| w.$spiXrst().set_bit());
+                    apb.rstr().modify(|_, w| w.$spiXrst().clear_bit());
 
                     // FRXTH: RXNE event is generated if the FIFO level is greater than or equal to
                     //        8-bit
@@ -97,8 +624,6 @@ macro_rules! hal {
                             w.frxth().set_bit().ds().bits(0b111).ssoe().clear_bit()
                         });
 
-                    let br = Self::compute_baud_rate(clocks.$pclkX(), freq.into());
-
                     // CPHA: phase
                     // CPOL: polarity
                     // MSTR: master mode
@@ -132,111 +657,1269 @@ macro_rules! hal {
                             .clear_bit()
                     });
 
-                    Spi { spi, pins }
+                    Ok(Spi {
+                        spi,
+                        pins,
+                        role: Master { sck },
+                    })
                 }
+            }
 
-                /// Change the baud rate of the SPI
-                pub fn reclock<F>(&mut self, freq: F, clocks: Clocks)
+            impl<PINS, ROLE: private::MasterRole> Spi<$SPIX, PINS, ROLE> {
+                /// Change the baud rate of the SPI, returning the actual SCK frequency
+                ///
+                /// See [`prescaler`] for how the frequency is picked.
+                pub fn reclock<F>(&mut self, freq: F, clocks: Clocks) -> Result<Hertz, Error>
                     where F: Into<Hertz>
                 {
+                    self.check_idle()?;
+                    let (br, sck) = prescaler(clocks.$pclkX(), freq.into(), $max)?;
                     self.spi.cr1.modify(|_, w| w.spe().clear_bit());
                     self.spi.cr1.modify(|_, w| {
-                        unsafe {w.br().bits(Self::compute_baud_rate(clocks.$pclkX(), freq.into()));}
+                        unsafe { w.br().bits(br); }
                         w.spe().set_bit()
                     });
+                    *self.role.sck_mut() = sck;
+                    Ok(sck)
                 }
 
-                fn compute_baud_rate(clocks: Hertz, freq: Hertz) -> u8 {
-                    match clocks.0 / freq.0 {
-                        0 => unreachable!(),
-                        1..=2 => 0b000,
-                        3..=5 => 0b001,
-                        6..=11 => 0b010,
-                        12..=23 => 0b011,
-                        24..=39 => 0b100,
-                        40..=95 => 0b101,
-                        96..=191 => 0b110,
-                        _ => 0b111,
-                    }
+                /// Returns the SCK frequency
+                pub fn frequency(&self) -> Hertz {
+                    self.role.sck()
                 }
+            }
 
-                /// Releases the SPI peripheral and associated pins
-                pub fn free(self) -> ($SPIX, (SCK, MISO, MOSI)) {
-                    (self.spi, self.pins)
+            impl<PINS, ROLE: private::Role> Spi<$SPIX, PINS, ROLE> {
+                /// Changes the data frame size
+                ///
+                /// Frames of up to 8 bits are exchanged as `u8`, larger ones as `u16`, other
+                /// word types fail with [`Error::WordSize`]. DMA transfers only support frames
+                /// of up to 8 bits.
+                pub fn set_data_size(&mut self, size: DataSize) -> Result<(), Error> {
+                    self.check_idle()?;
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    // FRXTH: RXNE event is generated once the FIFO holds a frame
+                    self.spi.cr2.modify(|_, w| unsafe {
+                        w.ds().bits(size.bits() - 1).frxth().bit(size.bits() <= 8)
+                    });
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                    Ok(())
                 }
-            }
 
-            impl<PINS> FullDuplex<u8> for Spi<$SPIX, PINS> {
-                type Error = Error;
+                /// Changes the order in which the bits of a frame are shifted out
+                pub fn set_bit_order(&mut self, order: BitOrder) -> Result<(), Error> {
+                    self.check_idle()?;
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| {
+                        w.lsbfirst().bit(order == BitOrder::LsbFirst).spe().set_bit()
+                    });
+                    Ok(())
+                }
 
-                fn read(&mut self) -> nb::Result<u8, Error> {
+                /// Clears the overrun, mode fault and CRC error flags, so transfers can continue
+                /// after an error
+                ///
+                /// Frames received before the overrun are dropped.
+                pub fn clear_errors(&mut self) {
                     let sr = self.spi.sr.read();
 
-                    Err(if sr.ovr().bit_is_set() {
-                        nb::Error::Other(Error::Overrun)
-                    } else if sr.modf().bit_is_set() {
-                        nb::Error::Other(Error::ModeFault)
-                    } else if sr.crcerr().bit_is_set() {
-                        nb::Error::Other(Error::Crc)
-                    } else if sr.rxne().bit_is_set() {
-                        // NOTE(read_volatile) read only 1 byte (the svd2rust API only allows
-                        // reading a half-word)
-                        return Ok(unsafe {
-                            ptr::read_volatile(&self.spi.dr as *const _ as *const u8)
-                        });
+                    if sr.ovr().bit_is_set() {
+                        // OVR is cleared by reading DR and then SR
+                        while self.spi.sr.read().frlvl().bits() != 0 {
+                            // NOTE(unsafe) see note in `read`
+                            let _ = unsafe {
+                                ptr::read_volatile(ptr::addr_of!(self.spi.dr) as *const u8)
+                            };
+                        }
+                        let _ = self.spi.sr.read();
+                    }
+                    if sr.modf().bit_is_set() {
+                        // MODF is cleared by the read of SR above followed by a write of CR1,
+                        // the mode fault also cleared MSTR and SPE
+                        self.spi.cr1.modify(|_, w| w.mstr().bit(ROLE::MASTER).spe().set_bit());
+                    }
+                    if sr.crcerr().bit_is_set() {
+                        self.spi.sr.write(|w| w.crcerr().clear_bit());
+                    }
+                }
+
+                /// Blocks until all written frames have been shifted out and the bus is idle
+                ///
+                /// Call this before releasing a chip select after sending frames.
+                pub fn flush(&mut self) -> Result<(), Error> {
+                    loop {
+                        let sr = self.spi.sr.read();
+                        if sr.modf().bit_is_set() {
+                            return Err(Error::ModeFault);
+                        } else if sr.ftlvl().bits() == 0 && sr.bsy().bit_is_clear() {
+                            return Ok(());
+                        }
+                    }
+                }
+
+                fn check_word<W: private::Word>(&self) -> Result<(), Error> {
+                    if W::fits(self.spi.cr2.read().ds().bits() + 1) {
+                        Ok(())
                     } else {
-                        nb::Error::WouldBlock
-                    })
+                        Err(Error::WordSize)
+                    }
                 }
 
-                fn send(&mut self, byte: u8) -> nb::Result<(), Error> {
+                fn check_idle(&self) -> Result<(), Error> {
                     let sr = self.spi.sr.read();
+                    if sr.bsy().bit_is_set() || sr.ftlvl().bits() != 0 {
+                        Err(Error::Busy)
+                    } else {
+                        Ok(())
+                    }
+                }
 
-                    Err(if sr.ovr().bit_is_set() {
-                        nb::Error::Other(Error::Overrun)
-                    } else if sr.modf().bit_is_set() {
-                        nb::Error::Other(Error::ModeFault)
-                    } else if sr.crcerr().bit_is_set() {
-                        nb::Error::Other(Error::Crc)
-                    } else if sr.txe().bit_is_set() {
-                        // NOTE(write_volatile) see note above
-                        unsafe { ptr::write_volatile(&self.spi.dr as *const _ as *mut u8, byte) }
+                /// Returns the fill level of the RX FIFO
+                pub fn rx_fifo_level(&self) -> FifoLevel {
+                    FifoLevel::from_bits(self.spi.sr.read().frlvl().bits())
+                }
+
+                /// Returns the fill level of the TX FIFO
+                pub fn tx_fifo_level(&self) -> FifoLevel {
+                    FifoLevel::from_bits(self.spi.sr.read().ftlvl().bits())
+                }
+            }
+
+            impl<PINS, ROLE: private::FullDuplexRole> Spi<$SPIX, PINS, ROLE> {
+                /// Enables hardware CRC calculation with the given length and polynomial
+                ///
+                /// The polynomial is given without its highest order bit, e.g. `0x07` for the
+                /// CRC-8 polynomial x^8 + x^2 + x + 1. Use [`write_with_crc`](Self::write_with_crc)
+                /// and [`transfer_with_crc`](Self::transfer_with_crc) to send and check the CRC.
+                pub fn enable_crc(
+                    &mut self,
+                    length: CrcLength,
+                    polynomial: u16,
+                ) -> Result<(), Error> {
+                    self.check_idle()?;
+                    // CRCEN and CRCL may only be written while the SPI is disabled
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit().crcen().clear_bit());
+                    self.spi.crcpr.write(|w| unsafe { w.crcpoly().bits(polynomial) });
+                    self.spi.cr1.modify(|_, w| {
+                        w.dff().bit(length == CrcLength::Bits16).crcen().set_bit()
+                    });
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                    Ok(())
+                }
+
+                /// Disables hardware CRC calculation
+                pub fn disable_crc(&mut self) -> Result<(), Error> {
+                    self.check_idle()?;
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.crcen().clear_bit().crcnext().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                    Ok(())
+                }
+
+                /// Returns the CRC calculated over the received frames
+                pub fn rx_crc(&self) -> u16 {
+                    self.spi.rxcrcr.read().rx_crc().bits()
+                }
+
+                /// Returns the CRC calculated over the transmitted frames
+                pub fn tx_crc(&self) -> u16 {
+                    self.spi.txcrcr.read().tx_crc().bits()
+                }
+
+                /// Writes `words` followed by their CRC and checks the CRC sent back by the
+                /// device
+                ///
+                /// CRC calculation must have been enabled with
+                /// [`enable_crc`](Self::enable_crc).
+                pub fn write_with_crc<W>(&mut self, words: &[W]) -> Result<(), Error>
+                where
+                    W: private::Word,
+                {
+                    if words.is_empty() {
                         return Ok(());
+                    }
+
+                    self.reset_crc();
+                    for (i, word) in words.iter().enumerate() {
+                        self.exchange_crc_frame(*word, i + 1 == words.len())?;
+                    }
+                    self.check_crc::<W>()
+                }
+
+                /// Transfers `words` followed by their CRC and checks the CRC sent back by the
+                /// device, replacing `words` with the received frames
+                ///
+                /// CRC calculation must have been enabled with
+                /// [`enable_crc`](Self::enable_crc).
+                pub fn transfer_with_crc<'w, W>(
+                    &mut self,
+                    words: &'w mut [W],
+                ) -> Result<&'w [W], Error>
+                where
+                    W: private::Word,
+                {
+                    if words.is_empty() {
+                        return Ok(words);
+                    }
+
+                    self.reset_crc();
+                    let len = words.len();
+                    for (i, word) in words.iter_mut().enumerate() {
+                        *word = self.exchange_crc_frame(*word, i + 1 == len)?;
+                    }
+                    self.check_crc::<W>()?;
+                    Ok(words)
+                }
+
+                fn reset_crc(&mut self) {
+                    // toggling CRCEN clears the CRC registers
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.crcen().clear_bit().crcnext().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.crcen().set_bit());
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                }
+
+                fn exchange_crc_frame<W>(&mut self, word: W, last: bool) -> Result<W, Error>
+                where
+                    W: private::Word,
+                {
+                    nb::block!(FullDuplex::send(self, word))?;
+                    if last {
+                        // CRCNEXT must be set right after the last frame was written
+                        self.spi.cr1.modify(|_, w| w.crcnext().set_bit());
+                    }
+                    nb::block!(FullDuplex::read(self))
+                }
+
+                fn check_crc<W: private::Word>(&mut self) -> Result<(), Error> {
+                    let cr1 = self.spi.cr1.read();
+                    let cr2 = self.spi.cr2.read();
+
+                    // a 16-bit CRC is received as two frames when the frames are 8 bits or less
+                    let frames = if cr1.dff().bit_is_set() && cr2.ds().bits() < 8 {
+                        2
                     } else {
-                        nb::Error::WouldBlock
-                    })
+                        1
+                    };
+                    for _ in 0..frames {
+                        while self.spi.sr.read().rxne().bit_is_clear() {}
+                        // NOTE(unsafe) see note in `read`, the CRC frames are discarded
+                        let _ = unsafe { W::read(ptr::addr_of!(self.spi.dr) as *const u32) };
+                    }
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+
+                    self.spi.cr1.modify(|_, w| w.crcnext().clear_bit());
+                    if self.spi.sr.read().crcerr().bit_is_set() {
+                        self.spi.sr.write(|w| w.crcerr().clear_bit());
+                        Err(Error::Crc)
+                    } else {
+                        Ok(())
+                    }
                 }
-            }
 
-            impl<PINS> crate::hal::blocking::spi::transfer::Default<u8> for Spi<$SPIX, PINS> {}
+                fn write_blocking<W: private::Word>(&mut self, words: &[W]) -> Result<(), Error> {
+                    for word in words {
+                        nb::block!(FullDuplex::send(self, *word))?;
+                        let _: W = nb::block!(FullDuplex::read(self))?;
+                    }
+                    self.flush()
+                }
 
-            impl<PINS> crate::hal::blocking::spi::write::Default<u8> for Spi<$SPIX, PINS> {}
-        )+
-    }
-}
+                fn transfer_blocking<'w, W: private::Word>(
+                    &mut self,
+                    words: &'w mut [W],
+                ) -> Result<&'w [W], Error> {
+                    for word in words.iter_mut() {
+                        nb::block!(FullDuplex::send(self, *word))?;
+                        *word = nb::block!(FullDuplex::read(self))?;
+                    }
+                    self.flush()?;
+                    Ok(words)
+                }
 
-//TODO: Confirm that SUBGHZSPI is actually SPI3
-use crate::pac::SPI3 as SUBGHZSPI;
+                /// Transfers `max(read.len(), write.len())` frames, sending `0x00` frames once
+                /// `write` is exhausted and dropping frames once `read` is full
+                #[cfg(feature = "eh1")]
+                fn transfer_split<W: private::Word>(
+                    &mut self,
+                    read: &mut [W],
+                    write: &[W],
+                ) -> Result<(), Error> {
+                    for i in 0..read.len().max(write.len()) {
+                        let word = write.get(i).copied().unwrap_or_default();
+                        nb::block!(FullDuplex::send(self, word))?;
+                        let word = nb::block!(FullDuplex::read(self))?;
+                        if let Some(slot) = read.get_mut(i) {
+                            *slot = word;
+                        }
+                    }
+                    self.flush()
+                }
 
-hal! {
-    SUBGHZSPI: (subghzspi, APB3, subghzspien, subghzspirst, pclk1),
-}
+                /// Writes `buffer` using DMA channel `tx`, discarding the received bytes
+                ///
+                /// Frames larger than 8 bits fail with [`Error::WordSize`], the peripheral,
+                /// channel and buffer are returned along with the error.
+                ///
+                /// # Panics
+                ///
+                /// If `buffer` is longer than 65535 bytes.
+                pub fn write_dma<B, TX>(
+                    self,
+                    mut tx: TX,
+                    buffer: B,
+                ) -> Result<dma::Transfer<B, TX, Self>, (Error, B, TX, Self)>
+                where
+                    B: StaticReadBuffer<Word = u8>,
+                    TX: dma::Channel,
+                {
+                    if let Err(e) = self.check_word::<u8>() {
+                        return Err((e, buffer, tx, self));
+                    }
 
-/*
-DEBUG_SUBGHZSPI_NSSOUT -> PA4
-DEBUG_SUBGHZSPI_SCKOUT -> PA5
-DEBUG_SUBGHZSPI_MISOOUT -> PA6
-DEBUG_SUBGHZSPI_MOSIOUT -> PA7
- */
+                    // NOTE(unsafe) the buffer is owned by the transfer until it is done
+                    let (ptr, len) = unsafe { buffer.read_buffer() };
 
-pins!(SUBGHZSPI, AF13,
-      SCK: [PA5],
-      MISO: [PA6],
-      MOSI: [PA7],
-      NSSOUT: [PA4]);
+                    tx.configure($txreq, Direction::MemoryToPeripheral, true);
+                    tx.set_addresses(self.dr_address(), ptr as u32);
+                    tx.set_transfer_length(self.dma_len(len));
 
-/*
+                    atomic::compiler_fence(Ordering::Release);
+
+                    tx.start();
+                    self.spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+
+                    Ok(dma::Transfer::new(buffer, tx, self))
+                }
+
+                /// Reads into `buffer` using DMA channel `rx`, clocking out `0x00` bytes with DMA
+                /// channel `tx`, like the embedded-hal 1.0 `SpiBus::read`
+                ///
+                /// Frames larger than 8 bits fail with [`Error::WordSize`], the peripheral,
+                /// channels and buffer are returned along with the error.
+                ///
+                /// # Panics
+                ///
+                /// If `buffer` is longer than 65535 bytes.
+                pub fn read_dma<B, RX, TX>(
+                    mut self,
+                    mut rx: RX,
+                    mut tx: TX,
+                    mut buffer: B,
+                ) -> Result<dma::Transfer<B, (RX, TX), Self>, (Error, B, (RX, TX), Self)>
+                where
+                    B: StaticWriteBuffer<Word = u8>,
+                    RX: dma::Channel,
+                    TX: dma::Channel,
+                {
+                    static DUMMY: u8 = 0x00;
+
+                    if let Err(e) = self.check_word::<u8>() {
+                        return Err((e, buffer, (rx, tx), self));
+                    }
+
+                    // NOTE(unsafe) the buffer is owned by the transfer until it is done
+                    let (ptr, len) = unsafe { buffer.write_buffer() };
+                    let len = self.dma_len(len);
+
+                    rx.configure($rxreq, Direction::PeripheralToMemory, true);
+                    rx.set_addresses(self.dr_address(), ptr as u32);
+                    rx.set_transfer_length(len);
+
+                    tx.configure($txreq, Direction::MemoryToPeripheral, false);
+                    tx.set_addresses(self.dr_address(), &DUMMY as *const u8 as u32);
+                    tx.set_transfer_length(len);
+
+                    self.start_full_duplex_dma(&mut rx, &mut tx);
+
+                    Ok(dma::Transfer::new(buffer, (rx, tx), self))
+                }
+
+                /// Writes `buffer` using DMA channel `tx` and replaces its contents with the
+                /// received bytes using DMA channel `rx`
+                ///
+                /// Frames larger than 8 bits fail with [`Error::WordSize`], the peripheral,
+                /// channels and buffer are returned along with the error.
+                ///
+                /// # Panics
+                ///
+                /// If `buffer` is longer than 65535 bytes.
+                pub fn transfer_dma<B, RX, TX>(
+                    mut self,
+                    mut rx: RX,
+                    mut tx: TX,
+                    mut buffer: B,
+                ) -> Result<dma::Transfer<B, (RX, TX), Self>, (Error, B, (RX, TX), Self)>
+                where
+                    B: StaticWriteBuffer<Word = u8>,
+                    RX: dma::Channel,
+                    TX: dma::Channel,
+                {
+                    if let Err(e) = self.check_word::<u8>() {
+                        return Err((e, buffer, (rx, tx), self));
+                    }
+
+                    // NOTE(unsafe) the buffer is owned by the transfer until it is done. Every
+                    // byte is sent before the byte received in its place is written back.
+                    let (ptr, len) = unsafe { buffer.write_buffer() };
+                    let len = self.dma_len(len);
+
+                    rx.configure($rxreq, Direction::PeripheralToMemory, true);
+                    rx.set_addresses(self.dr_address(), ptr as u32);
+                    rx.set_transfer_length(len);
+
+                    tx.configure($txreq, Direction::MemoryToPeripheral, true);
+                    tx.set_addresses(self.dr_address(), ptr as u32);
+                    tx.set_transfer_length(len);
+
+                    self.start_full_duplex_dma(&mut rx, &mut tx);
+
+                    Ok(dma::Transfer::new(buffer, (rx, tx), self))
+                }
+
+                fn start_full_duplex_dma<RX, TX>(&mut self, rx: &mut RX, tx: &mut TX)
+                where
+                    RX: dma::Channel,
+                    TX: dma::Channel,
+                {
+                    atomic::compiler_fence(Ordering::Release);
+
+                    // enable order from the reference manual: RX requests, channels, TX requests
+                    self.spi.cr2.modify(|_, w| w.rxdmaen().set_bit());
+                    rx.start();
+                    tx.start();
+                    self.spi.cr2.modify(|_, w| w.txdmaen().set_bit());
+                }
+
+                fn dr_address(&self) -> u32 {
+                    ptr::addr_of!(self.spi.dr) as u32
+                }
+
+                fn dma_len(&self, len: usize) -> u16 {
+                    assert!(len <= u16::MAX as usize, "DMA transfers are limited to 65535 bytes");
+                    len as u16
+                }
+            }
+
+            impl<PINS, ROLE> TransferPayload for Spi<$SPIX, PINS, ROLE>
+            where
+                ROLE: private::FullDuplexRole,
+            {
+                fn stop(&mut self) {
+                    // wait until the last byte left the TX FIFO and the shift register
+                    while self.spi.sr.read().ftlvl().bits() != 0 {}
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+
+                    self.spi
+                        .cr2
+                        .modify(|_, w| w.txdmaen().clear_bit().rxdmaen().clear_bit());
+
+                    // drop the bytes received during a write, this also clears the overrun flag
+                    // they may have caused
+                    while self.spi.sr.read().frlvl().bits() != 0 {
+                        // NOTE(read_volatile) see note in `read`
+                        let _ = unsafe {
+                            ptr::read_volatile(ptr::addr_of!(self.spi.dr) as *const u8)
+                        };
+                    }
+                    let _ = self.spi.sr.read();
+
+                    self.role.dma_stopped();
+                }
+            }
+
+            impl<PINS, ROLE, W> FullDuplex<W> for Spi<$SPIX, PINS, ROLE>
+            where
+                ROLE: private::FullDuplexRole,
+                W: private::Word,
+            {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<W, Error> {
+                    self.check_word::<W>()?;
+                    let sr = self.spi.sr.read();
+
+                    Err(if sr.ovr().bit_is_set() {
+                        nb::Error::Other(Error::Overrun)
+                    } else if sr.modf().bit_is_set() {
+                        nb::Error::Other(Error::ModeFault)
+                    } else if sr.crcerr().bit_is_set() {
+                        nb::Error::Other(Error::Crc)
+                    } else if sr.tifrfe().bit_is_set() {
+                        nb::Error::Other(Error::FrameFormat)
+                    } else if sr.rxne().bit_is_set() {
+                        // NOTE(unsafe) read only 1 frame, with an access of its width (the
+                        // svd2rust API only allows reading a word)
+                        let word = unsafe { W::read(ptr::addr_of!(self.spi.dr) as *const u32) };
+                        self.role.received();
+                        return Ok(word);
+                    } else {
+                        nb::Error::WouldBlock
+                    })
+                }
+
+                fn send(&mut self, word: W) -> nb::Result<(), Error> {
+                    self.check_word::<W>()?;
+                    let sr = self.spi.sr.read();
+
+                    Err(if sr.ovr().bit_is_set() {
+                        nb::Error::Other(Error::Overrun)
+                    } else if sr.modf().bit_is_set() {
+                        nb::Error::Other(Error::ModeFault)
+                    } else if sr.crcerr().bit_is_set() {
+                        nb::Error::Other(Error::Crc)
+                    } else if sr.tifrfe().bit_is_set() {
+                        nb::Error::Other(Error::FrameFormat)
+                    } else if sr.txe().bit_is_set() {
+                        // NOTE(unsafe) see note above
+                        unsafe { W::write(ptr::addr_of!(self.spi.dr) as *const u32, word) }
+                        self.role.sent();
+                        return Ok(());
+                    } else {
+                        nb::Error::WouldBlock
+                    })
+                }
+            }
+
+            // the blocking transfers wait for the bus to be idle before returning, so a chip select
+            // can be released right after them
+
+            impl<PINS, ROLE: private::FullDuplexRole> Transfer<u8> for Spi<$SPIX, PINS, ROLE> {
+                type Error = Error;
+
+                fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Error> {
+                    self.transfer_blocking(words)
+                }
+            }
+
+            impl<PINS, ROLE: private::FullDuplexRole> Write<u8> for Spi<$SPIX, PINS, ROLE> {
+                type Error = Error;
+
+                fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+                    self.write_blocking(words)
+                }
+            }
+
+            impl<PINS, ROLE: private::FullDuplexRole> Transfer<u16> for Spi<$SPIX, PINS, ROLE> {
+                type Error = Error;
+
+                fn transfer<'w>(&mut self, words: &'w mut [u16]) -> Result<&'w [u16], Error> {
+                    self.transfer_blocking(words)
+                }
+            }
+
+            impl<PINS, ROLE: private::FullDuplexRole> Write<u16> for Spi<$SPIX, PINS, ROLE> {
+                type Error = Error;
+
+                fn write(&mut self, words: &[u16]) -> Result<(), Error> {
+                    self.write_blocking(words)
+                }
+            }
+
+            #[cfg(feature = "eh1")]
+            impl<PINS> embedded_hal_1::spi::ErrorType for Spi<$SPIX, PINS> {
+                type Error = Error;
+            }
+
+            #[cfg(feature = "eh1")]
+            impl<PINS> embedded_hal_1::spi::SpiBus<u8> for Spi<$SPIX, PINS> {
+                fn read(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    self.transfer_split(words, &[])
+                }
+
+                fn write(&mut self, words: &[u8]) -> Result<(), Error> {
+                    self.write_blocking(words)
+                }
+
+                fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Error> {
+                    self.transfer_split(read, write)
+                }
+
+                fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Error> {
+                    self.transfer_blocking(words).map(|_| ())
+                }
+
+                fn flush(&mut self) -> Result<(), Error> {
+                    Spi::<$SPIX, PINS>::flush(self)
+                }
+            }
+
+            #[cfg(feature = "eh1")]
+            impl<PINS> embedded_hal_1::spi::SpiBus<u16> for Spi<$SPIX, PINS> {
+                fn read(&mut self, words: &mut [u16]) -> Result<(), Error> {
+                    self.transfer_split(words, &[])
+                }
+
+                fn write(&mut self, words: &[u16]) -> Result<(), Error> {
+                    self.write_blocking(words)
+                }
+
+                fn transfer(&mut self, read: &mut [u16], write: &[u16]) -> Result<(), Error> {
+                    self.transfer_split(read, write)
+                }
+
+                fn transfer_in_place(&mut self, words: &mut [u16]) -> Result<(), Error> {
+                    self.transfer_blocking(words).map(|_| ())
+                }
+
+                fn flush(&mut self) -> Result<(), Error> {
+                    Spi::<$SPIX, PINS>::flush(self)
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! half_duplex {
+    ($($SPIX:ident: ($spiX_half_duplex:ident, $APBX:ident, $spiXen:ident, $spiXrst:ident,
+                     $pclkX:ident),)+) => {
+        $(
+            impl<SCK, MOSI> Spi<$SPIX, (SCK, MOSI), HalfDuplex> {
+                /// Configures the SPI peripheral to operate in half duplex (3-wire) master mode
+                ///
+                /// Data is both sent and received on the MOSI pin, with explicit
+                /// [`write_half_duplex`](Self::write_half_duplex) and
+                /// [`read_half_duplex`](Self::read_half_duplex) phases. If `freq` can't be
+                /// reached, the peripheral and pins are returned along with the error.
+                pub fn $spiX_half_duplex<F>(
+                    spi: $SPIX,
+                    pins: (SCK, MOSI),
+                    mode: Mode,
+                    freq: F,
+                    clocks: Clocks,
+                    apb: &mut $APBX,
+                ) -> Result<Self, (Error, $SPIX, (SCK, MOSI))>
+                where
+                    F: Into<Hertz>,
+                    SCK: SckPin<$SPIX>,
+                    MOSI: MosiPin<$SPIX>,
+                {
+                    let (br, sck) = match prescaler(clocks.$pclkX(), freq.into(), None) {
+                        Ok(prescaler) => prescaler,
+                        Err(e) => return Err((e, spi, pins)),
+                    };
+
+                    // enable or reset $SPIX
+                    apb.enr().modify(|_, w| w.$spiXen().set_bit());
+                    apb.rstr().modify(|_, w| w.$spiXrst().set_bit());
+                    apb.rstr().modify(|_, w| w.$spiXrst().clear_bit());
+
+                    // FRXTH: RXNE event is generated if the FIFO level is greater than or equal to
+                    //        8-bit
+                    // DS: 8-bit data size
+                    // SSOE: Slave Select output disabled
+                    spi.cr2
+                        .write(|w| unsafe {
+                            w.frxth().set_bit().ds().bits(0b111).ssoe().clear_bit()
+                        });
+
+                    // CPHA: phase
+                    // CPOL: polarity
+                    // MSTR: master mode
+                    // SPE: SPI enabled, no clock is generated until data is written
+                    // LSBFIRST: MSB first
+                    // SSM: enable software slave management (NSS pin free for other uses)
+                    // SSI: set nss high = master mode
+                    // CRCEN: hardware CRC calculation disabled
+                    // BIDIMODE: 1 line bidirectional (half duplex)
+                    // BIDIOE: output enabled, the data line is driven while idle
+                    spi.cr1.write(|w| unsafe {
+                        w.cpha()
+                            .bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .cpol()
+                            .bit(mode.polarity == Polarity::IdleHigh)
+                            .mstr()
+                            .set_bit()
+                            .br()
+                            .bits(br)
+                            .spe()
+                            .set_bit()
+                            .lsbfirst()
+                            .clear_bit()
+                            .ssi()
+                            .set_bit()
+                            .ssm()
+                            .set_bit()
+                            .crcen()
+                            .clear_bit()
+                            .bidimode()
+                            .set_bit()
+                            .bidioe()
+                            .set_bit()
+                    });
+
+                    Ok(Spi {
+                        spi,
+                        pins,
+                        role: HalfDuplex { sck },
+                    })
+                }
+
+                /// Writes `words` on the data line
+                pub fn write_half_duplex<W>(&mut self, words: &[W]) -> Result<(), Error>
+                where
+                    W: private::Word,
+                {
+                    self.check_word::<W>()?;
+                    for word in words {
+                        loop {
+                            let sr = self.spi.sr.read();
+                            if sr.modf().bit_is_set() {
+                                return Err(Error::ModeFault);
+                            } else if sr.txe().bit_is_set() {
+                                break;
+                            }
+                        }
+                        // NOTE(unsafe) see note in `send`
+                        unsafe { W::write(ptr::addr_of!(self.spi.dr) as *const u32, *word) }
+                    }
+
+                    // wait until the last frame was shifted out
+                    while self.spi.sr.read().ftlvl().bits() != 0 {}
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+                    Ok(())
+                }
+
+                /// Reads `words` from the data line
+                ///
+                /// In receive mode the clock runs continuously, it is stopped by disabling the
+                /// SPI while the last frame is received.
+                pub fn read_half_duplex<W>(&mut self, words: &mut [W]) -> Result<(), Error>
+                where
+                    W: private::Word,
+                {
+                    self.check_word::<W>()?;
+                    if words.is_empty() {
+                        return Ok(());
+                    }
+
+                    // switching to input starts the clock
+                    self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.bidioe().clear_bit());
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+                    if words.len() == 1 {
+                        self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                    }
+
+                    let last = words.len() - 1;
+                    let mut result = Ok(());
+                    for (i, word) in words.iter_mut().enumerate() {
+                        loop {
+                            let sr = self.spi.sr.read();
+                            if sr.ovr().bit_is_set() {
+                                result = Err(Error::Overrun);
+                            } else if sr.modf().bit_is_set() {
+                                result = Err(Error::ModeFault);
+                            } else if sr.rxne().bit_is_clear() {
+                                continue;
+                            }
+                            break;
+                        }
+                        if result.is_err() {
+                            self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                            break;
+                        }
+
+                        // NOTE(unsafe) see note in `read`
+                        *word = unsafe { W::read(ptr::addr_of!(self.spi.dr) as *const u32) };
+                        if i + 1 == last {
+                            self.spi.cr1.modify(|_, w| w.spe().clear_bit());
+                        }
+                    }
+
+                    // drop any frames received after the SPI was disabled, then drive the data
+                    // line again
+                    while self.spi.sr.read().bsy().bit_is_set() {}
+                    while self.spi.sr.read().frlvl().bits() != 0 {
+                        // NOTE(unsafe) see note in `read`
+                        let _ = unsafe { W::read(ptr::addr_of!(self.spi.dr) as *const u32) };
+                    }
+                    let _ = self.spi.sr.read();
+                    self.spi.cr1.modify(|_, w| w.bidioe().set_bit());
+                    self.spi.cr1.modify(|_, w| w.spe().set_bit());
+
+                    result
+                }
+
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, (SCK, MOSI)) {
+                    (self.spi, self.pins)
+                }
+            }
+        )+
+    }
+}
+
+macro_rules! nss {
+    ($($SPIX:ident: ($spiX_hw_nss:ident, $spiX_slave:ident, $APBX:ident, $spiXen:ident,
+                     $spiXrst:ident, $pclkX:ident),)+) => {
+        $(
+            impl<SCK, MISO, MOSI, NSS> Spi<$SPIX, (SCK, MISO, MOSI, NSS)> {
+                /// Configures the SPI peripheral to operate in full duplex master mode, driving
+                /// the NSS pin
+                ///
+                /// NSS pulses require a mode that captures data on the first clock transition,
+                /// other modes fail with [`Error::NssPulsePhase`]. On failure the peripheral and
+                /// pins are returned along with the error.
+                pub fn $spiX_hw_nss<F>(
+                    spi: $SPIX,
+                    pins: (SCK, MISO, MOSI, NSS),
+                    mode: Mode,
+                    nss_mode: NssMode,
+                    freq: F,
+                    clocks: Clocks,
+                    apb: &mut $APBX,
+                ) -> Result<Self, (Error, $SPIX, (SCK, MISO, MOSI, NSS))>
+                where
+                    F: Into<Hertz>,
+                    SCK: SckPin<$SPIX>,
+                    MISO: MisoPin<$SPIX>,
+                    MOSI: MosiPin<$SPIX>,
+                    NSS: NssPin<$SPIX>,
+                {
+                    if nss_mode == NssMode::Pulse
+                        && mode.phase == Phase::CaptureOnSecondTransition
+                    {
+                        return Err((Error::NssPulsePhase, spi, pins));
+                    }
+
+                    let (br, sck) = match prescaler(clocks.$pclkX(), freq.into(), None) {
+                        Ok(prescaler) => prescaler,
+                        Err(e) => return Err((e, spi, pins)),
+                    };
+
+                    // enable or reset $SPIX
+                    apb.enr().modify(|_, w| w.$spiXen().set_bit());
+                    apb.rstr().modify(|_, w| w.$spiXrst().set_bit());
+                    apb.rstr().modify(|_, w| w.$spiXrst().clear_bit());
+
+                    // FRXTH: RXNE event is generated if the FIFO level is greater than or equal to
+                    //        8-bit
+                    // DS: 8-bit data size
+                    // SSOE: Slave Select output enabled
+                    // NSSP: NSS pulse between frames
+                    spi.cr2.write(|w| unsafe {
+                        w.frxth()
+                            .set_bit()
+                            .ds()
+                            .bits(0b111)
+                            .ssoe()
+                            .set_bit()
+                            .nssp()
+                            .bit(nss_mode == NssMode::Pulse)
+                    });
+
+                    // CPHA: phase
+                    // CPOL: polarity
+                    // MSTR: master mode
+                    // SPE: SPI enabled, which drives NSS low
+                    // LSBFIRST: MSB first
+                    // SSM: hardware slave management
+                    // CRCEN: hardware CRC calculation disabled
+                    // BIDIMODE: 2 line unidirectional (full duplex)
+                    spi.cr1.write(|w| unsafe {
+                        w.cpha()
+                            .bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .cpol()
+                            .bit(mode.polarity == Polarity::IdleHigh)
+                            .mstr()
+                            .set_bit()
+                            .br()
+                            .bits(br)
+                            .spe()
+                            .set_bit()
+                            .lsbfirst()
+                            .clear_bit()
+                            .ssm()
+                            .clear_bit()
+                            .crcen()
+                            .clear_bit()
+                            .bidimode()
+                            .clear_bit()
+                    });
+
+                    Ok(Spi {
+                        spi,
+                        pins,
+                        role: Master { sck },
+                    })
+                }
+
+            }
+
+            impl<SCK, MISO, MOSI, NSS> Spi<$SPIX, (SCK, MISO, MOSI, NSS), Slave> {
+                /// Configures the SPI peripheral to operate in full duplex slave mode
+                ///
+                /// The master selects the slave by pulling NSS low. Frames to send must be
+                /// written before the master clocks them out, up to four can be queued in the TX
+                /// FIFO. A frame clocked out by the master that was not written is reported by
+                /// [`take_underrun`](Spi::take_underrun).
+                pub fn $spiX_slave(
+                    spi: $SPIX,
+                    pins: (SCK, MISO, MOSI, NSS),
+                    mode: Mode,
+                    apb: &mut $APBX,
+                ) -> Self
+                where
+                    SCK: SckPin<$SPIX>,
+                    MISO: MisoPin<$SPIX>,
+                    MOSI: MosiPin<$SPIX>,
+                    NSS: NssPin<$SPIX>,
+                {
+                    // enable or reset $SPIX
+                    apb.enr().modify(|_, w| w.$spiXen().set_bit());
+                    apb.rstr().modify(|_, w| w.$spiXrst().set_bit());
+                    apb.rstr().modify(|_, w| w.$spiXrst().clear_bit());
+
+                    // FRXTH: RXNE event is generated if the FIFO level is greater than or equal to
+                    //        8-bit
+                    // DS: 8-bit data size
+                    spi.cr2
+                        .write(|w| unsafe { w.frxth().set_bit().ds().bits(0b111) });
+
+                    // CPHA: phase
+                    // CPOL: polarity
+                    // MSTR: slave mode
+                    // SPE: SPI enabled
+                    // LSBFIRST: MSB first
+                    // SSM: hardware slave management, selected by the NSS pin
+                    // CRCEN: hardware CRC calculation disabled
+                    // BIDIMODE: 2 line unidirectional (full duplex)
+                    spi.cr1.write(|w| {
+                        w.cpha()
+                            .bit(mode.phase == Phase::CaptureOnSecondTransition)
+                            .cpol()
+                            .bit(mode.polarity == Polarity::IdleHigh)
+                            .mstr()
+                            .clear_bit()
+                            .spe()
+                            .set_bit()
+                            .lsbfirst()
+                            .clear_bit()
+                            .ssm()
+                            .clear_bit()
+                            .crcen()
+                            .clear_bit()
+                            .bidimode()
+                            .clear_bit()
+                    });
+
+                    Spi {
+                        spi,
+                        pins,
+                        role: Slave {
+                            pending: 0,
+                            underrun: false,
+                        },
+                    }
+                }
+            }
+
+            impl<SCK, MISO, MOSI, NSS, ROLE> Spi<$SPIX, (SCK, MISO, MOSI, NSS), ROLE> {
+                /// Releases the SPI peripheral and associated pins
+                pub fn free(self) -> ($SPIX, (SCK, MISO, MOSI, NSS)) {
+                    (self.spi, self.pins)
+                }
+            }
+        )+
+    }
+}
+
+half_duplex! {
+    SPI1: (spi1_half_duplex, APB2, spi1en, spi1rst, pclk2),
+    SPI2: (spi2_half_duplex, APB1R1, spi2s2en, spi2s2rst, pclk1),
+}
+
+nss! {
+    SPI1: (spi1_hw_nss, spi1_slave, APB2, spi1en, spi1rst, pclk2),
+    SPI2: (spi2_hw_nss, spi2_slave, APB1R1, spi2s2en, spi2s2rst, pclk1),
+}
+
+//TODO: Confirm that SUBGHZSPI is actually SPI3
+pub(crate) use crate::pac::SPI3 as SUBGHZSPI;
+
+hal! {
+    SPI1: (spi1, APB2, spi1en, spi1rst, pclk2, None, 7, 8),
+    SPI2: (spi2, APB1R1, spi2s2en, spi2s2rst, pclk1, None, 9, 10),
+    SUBGHZSPI: (subghzspi, APB3, subghzspien, subghzspirst, pclk3, Some(SUBGHZSPI_MAX_FREQ),
+                41, 42),
+}
+
+impl Spi<SUBGHZSPI, ()> {
+    /// Configures SUBGHZSPI for the sub-GHz radio, in mode 0 at the fastest SCK it supports
+    pub(crate) fn subghz(spi: SUBGHZSPI, clocks: Clocks, apb: &mut APB3) -> Result<Self, Error> {
+        let freq = Hertz(core::cmp::min(clocks.pclk3().0 / 2, SUBGHZSPI_MAX_FREQ.0));
+        // this frequency is always within range
+        Self::new_master(spi, (), MODE_0, freq, clocks, apb).map_err(|(e, _, _)| e)
+    }
+
+    /// Releases the SPI peripheral
+    pub(crate) fn free(self) -> SUBGHZSPI {
+        self.spi
+    }
+}
+
+pins!(SPI1, AF5,
+      SCK: [PA1, PA5, PB3],
+      MISO: [PA6, PA11, PB4],
+      MOSI: [PA7, PA12, PB5],
+      NSSOUT: [PA4, PA15, PB2]);
+
+pins!(SPI2, AF5,
+      SCK: [PA8, PA9, PB10, PB13],
+      MISO: [PB14, PC2],
+      MOSI: [PA10, PB15, PC3],
+      NSSOUT: [PB9, PB12]);
+
+pins!(SPI2, AF3,
+      SCK: [],
+      MISO: [PA5],
+      MOSI: [PC1],
+      NSSOUT: [PA9]);
+
+/*
+DEBUG_SUBGHZSPI_NSSOUT -> PA4
+DEBUG_SUBGHZSPI_SCKOUT -> PA5
+DEBUG_SUBGHZSPI_MISOOUT -> PA6
+DEBUG_SUBGHZSPI_MOSIOUT -> PA7
+ */
+
+pins!(SUBGHZSPI, AF13,
+      SCK: [PA5],
+      MISO: [PA6],
+      MOSI: [PA7],
+      NSSOUT: [PA4]);
+
+/*
 The sub-GHz radio SPI clock is derived from the PCLK3 clock. The SUBGHZSPI_SCK
 frequency is obtained by PCLK3 divided by two. The SUBGHZSPI_SCK clock maximum
 speed must not exceed 16 MHz.
 */
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PCLK: Hertz = Hertz(48_000_000);
+
+    /// Returns the `BR` value and SCK frequency in Hz for `freq`
+    fn sck(freq: u32, max: Option<Hertz>) -> (u8, u32) {
+        let (br, sck) = prescaler(PCLK, Hertz(freq), max).unwrap();
+        (br, sck.0)
+    }
+
+    #[test]
+    fn exact_divider_is_used() {
+        assert_eq!(sck(24_000_000, None), (0, 24_000_000));
+        assert_eq!(sck(3_000_000, None), (3, 3_000_000));
+        assert_eq!(sck(187_500, None), (7, 187_500));
+    }
+
+    #[test]
+    fn frequency_is_rounded_down() {
+        // 48 MHz / 16 MHz = 3, divided by 4 instead
+        assert_eq!(sck(16_000_000, Some(SUBGHZSPI_MAX_FREQ)), (1, 12_000_000));
+        assert_eq!(sck(1_000_000, None), (5, 750_000));
+    }
+
+    #[test]
+    fn out_of_range_is_rejected() {
+        assert!(matches!(
+            prescaler(PCLK, Hertz(24_000_001), None),
+            Err(Error::FrequencyTooHigh)
+        ));
+        assert!(matches!(
+            prescaler(PCLK, Hertz(20_000_000), Some(SUBGHZSPI_MAX_FREQ)),
+            Err(Error::FrequencyTooHigh)
+        ));
+        assert!(matches!(
+            prescaler(PCLK, Hertz(187_499), None),
+            Err(Error::FrequencyTooLow)
+        ));
+        assert!(matches!(prescaler(PCLK, Hertz(0), None), Err(Error::FrequencyTooLow)));
+        // 1 MHz / 256 = 3906.25 Hz, which is above the requested frequency
+        assert!(matches!(
+            prescaler(Hertz(1_000_000), Hertz(3_906), None),
+            Err(Error::FrequencyTooLow)
+        ));
+    }
+
+    #[cfg(feature = "eh1")]
+    mod device {
+        extern crate std;
+
+        use super::super::*;
+        use core::convert::Infallible;
+        use embedded_hal_1::delay::DelayNs;
+        use embedded_hal_1::spi::{ErrorType, Operation, SpiBus, SpiDevice as _};
+        use std::vec::Vec;
+
+        type Log = RefCell<Vec<Op>>;
+
+        #[derive(Debug, PartialEq)]
+        enum Op {
+            Select,
+            Deselect,
+            Write(Vec<u8>),
+            Read(usize),
+            Flush,
+            Delay(u32),
+        }
+
+        /// Bus that answers every read with `0xA5`, failing every write if `fail` is set
+        struct MockBus<'a> {
+            log: &'a Log,
+            fail: bool,
+        }
+
+        impl ErrorType for MockBus<'_> {
+            type Error = embedded_hal_1::spi::ErrorKind;
+        }
+
+        impl SpiBus for MockBus<'_> {
+            fn read(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+                self.log.borrow_mut().push(Op::Read(words.len()));
+                words.fill(0xA5);
+                Ok(())
+            }
+
+            fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+                self.log.borrow_mut().push(Op::Write(words.to_vec()));
+                if self.fail {
+                    return Err(embedded_hal_1::spi::ErrorKind::Other);
+                }
+                Ok(())
+            }
+
+            fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Self::Error> {
+                self.write(write)?;
+                self.read(read)
+            }
+
+            fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Self::Error> {
+                self.write(words)?;
+                self.read(words)
+            }
+
+            fn flush(&mut self) -> Result<(), Self::Error> {
+                self.log.borrow_mut().push(Op::Flush);
+                Ok(())
+            }
+        }
+
+        struct MockCs<'a>(&'a Log);
+
+        impl OutputPin for MockCs<'_> {
+            type Error = Infallible;
+
+            fn set_low(&mut self) -> Result<(), Self::Error> {
+                self.0.borrow_mut().push(Op::Select);
+                Ok(())
+            }
+
+            fn set_high(&mut self) -> Result<(), Self::Error> {
+                self.0.borrow_mut().push(Op::Deselect);
+                Ok(())
+            }
+        }
+
+        struct MockDelay<'a>(&'a Log);
+
+        impl DelayNs for MockDelay<'_> {
+            fn delay_ns(&mut self, ns: u32) {
+                self.0.borrow_mut().push(Op::Delay(ns));
+            }
+        }
+
+        #[test]
+        fn transaction_is_selected_and_flushed() {
+            let log = Log::new(Vec::new());
+            let bus = SharedBus::new(MockBus {
+                log: &log,
+                fail: false,
+            });
+            let mut device = bus.device_with_delay(MockCs(&log), MockDelay(&log));
+
+            let mut buf = [0; 2];
+            device
+                .transaction(&mut [
+                    Operation::Write(&[0x01]),
+                    Operation::DelayNs(100),
+                    Operation::Read(&mut buf),
+                ])
+                .unwrap();
+
+            assert_eq!(buf, [0xA5; 2]);
+            assert_eq!(
+                *log.borrow(),
+                [
+                    Op::Select,
+                    Op::Write(Vec::from([0x01])),
+                    Op::Flush,
+                    Op::Delay(100),
+                    Op::Read(2),
+                    Op::Flush,
+                    Op::Deselect,
+                ]
+            );
+        }
+
+        #[test]
+        fn busy_bus_is_reported() {
+            let log = Log::new(Vec::new());
+            let bus = SharedBus::new(MockBus {
+                log: &log,
+                fail: false,
+            });
+            let mut device = bus.device_with_delay(MockCs(&log), MockDelay(&log));
+
+            let _transfer = bus.spi.borrow_mut();
+            assert!(matches!(device.write(&[0]), Err(DeviceError::BusBusy)));
+            assert!(log.borrow().is_empty());
+        }
+
+        #[test]
+        fn devices_are_selected_per_transaction() {
+            let log = Log::new(Vec::new());
+            let bus = SharedBus::new(MockBus {
+                log: &log,
+                fail: false,
+            });
+            let mut a = bus.device_with_delay(MockCs(&log), MockDelay(&log));
+            let mut b = bus.device_with_delay(MockCs(&log), MockDelay(&log));
+
+            a.write(&[0x01]).unwrap();
+            b.write(&[0x02]).unwrap();
+
+            assert_eq!(
+                *log.borrow(),
+                [
+                    Op::Select,
+                    Op::Write(Vec::from([0x01])),
+                    Op::Flush,
+                    Op::Deselect,
+                    Op::Select,
+                    Op::Write(Vec::from([0x02])),
+                    Op::Flush,
+                    Op::Deselect,
+                ]
+            );
+        }
+
+        #[test]
+        fn device_is_deselected_on_error() {
+            let log = Log::new(Vec::new());
+            let bus = SharedBus::new(MockBus {
+                log: &log,
+                fail: true,
+            });
+            let mut device = bus.device_with_delay(MockCs(&log), MockDelay(&log));
+
+            assert!(matches!(device.write(&[0x01]), Err(DeviceError::Spi(_))));
+            assert_eq!(
+                *log.borrow(),
+                [Op::Select, Op::Write(Vec::from([0x01])), Op::Deselect]
+            );
+        }
+    }
+}
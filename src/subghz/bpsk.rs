@@ -0,0 +1,74 @@
+//! BPSK modulation and packet parameters
+//!
+//! The radio can only transmit BPSK, at the 100 and 600 bit/s used by Sigfox uplinks. The
+//! payload is sent as is, so framing and differential encoding are up to the application.
+
+use super::{opcode, Error, SubGhz};
+
+/// PA ramp-up timing registers
+const REG_RAMP_UP: u16 = 0x00F0;
+/// PA ramp-down timing registers
+const REG_RAMP_DOWN: u16 = 0x00F2;
+
+/// DBPSK pulse shape, the only one supported in BPSK mode
+const PULSE_SHAPE_DBPSK: u8 = 0x16;
+
+/// BPSK bit rate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BpskBitRate {
+    /// 100 bit/s
+    Bps100,
+    /// 600 bit/s
+    Bps600,
+}
+
+impl BpskBitRate {
+    /// Returns the bit rate register value, `32 * 32 MHz / bit rate`
+    fn br_bits(self) -> u32 {
+        match self {
+            BpskBitRate::Bps100 => 10_240_000,
+            BpskBitRate::Bps600 => 1_706_666,
+        }
+    }
+
+    /// Returns the PA ramp-up timing that cleanly starts a frame at this bit rate
+    fn ramp_up(self) -> u16 {
+        match self {
+            BpskBitRate::Bps100 => 0x370F,
+            BpskBitRate::Bps600 => 0x092F,
+        }
+    }
+
+    /// Returns the PA ramp-down timing that cleanly ends a frame at this bit rate
+    fn ramp_down(self) -> u16 {
+        match self {
+            BpskBitRate::Bps100 => 0x1D70,
+            BpskBitRate::Bps600 => 0x04E1,
+        }
+    }
+}
+
+impl SubGhz {
+    /// Sets the BPSK modulation parameters
+    ///
+    /// This also adjusts the PA ramp-up and ramp-down timings to the bit rate, so the start and
+    /// end of the frame don't spill over into neighbouring channels.
+    pub fn set_bpsk_mod_params(&mut self, bit_rate: BpskBitRate) -> Result<(), Error> {
+        let [_, br2, br1, br0] = bit_rate.br_bits().to_be_bytes();
+        self.write(&[
+            opcode::SET_MODULATION_PARAMS,
+            br2,
+            br1,
+            br0,
+            PULSE_SHAPE_DBPSK,
+        ])?;
+
+        self.write_register(REG_RAMP_UP, &bit_rate.ramp_up().to_be_bytes())?;
+        self.write_register(REG_RAMP_DOWN, &bit_rate.ramp_down().to_be_bytes())
+    }
+
+    /// Sets the BPSK packet parameters, the payload length in bytes
+    pub fn set_bpsk_packet_params(&mut self, payload_len: u8) -> Result<(), Error> {
+        self.write(&[opcode::SET_PACKET_PARAMS, payload_len])
+    }
+}
@@ -0,0 +1,123 @@
+//! Channel activity detection and listen before talk
+
+use super::status::rssi_dbm;
+use super::{opcode, ChipMode, Error, StandbyClk, SubGhz, Timeout};
+use crate::time::{Hertz, MilliSeconds};
+
+/// Number of symbols used for LoRa channel activity detection
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CadSymbols {
+    /// 1 symbol
+    One,
+    /// 2 symbols
+    Two,
+    /// 4 symbols
+    Four,
+    /// 8 symbols
+    Eight,
+    /// 16 symbols
+    Sixteen,
+}
+
+/// What the radio does once channel activity detection is done
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CadExitMode {
+    /// Go back to standby
+    CadOnly,
+    /// Receive for the CAD timeout if activity was detected, otherwise go back to standby
+    CadRx,
+    /// Transmit if no activity was detected, otherwise go back to standby
+    CadLbt,
+}
+
+/// LoRa channel activity detection parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CadParams {
+    /// Number of symbols to detect
+    pub symbols: CadSymbols,
+    /// Detection peak threshold, depends on the spreading factor and bandwidth
+    pub det_peak: u8,
+    /// Minimum detection peak
+    pub det_min: u8,
+    /// Exit mode
+    pub exit_mode: CadExitMode,
+    /// Timeout of the RX or TX following the detection, see [`CadExitMode`]
+    pub timeout: Timeout,
+}
+
+impl SubGhz {
+    /// Sets the LoRa channel activity detection parameters
+    pub fn set_cad_params(&mut self, params: &CadParams) -> Result<(), Error> {
+        let symbols = match params.symbols {
+            CadSymbols::One => 0x00,
+            CadSymbols::Two => 0x01,
+            CadSymbols::Four => 0x02,
+            CadSymbols::Eight => 0x03,
+            CadSymbols::Sixteen => 0x04,
+        };
+        let exit_mode = match params.exit_mode {
+            CadExitMode::CadOnly => 0x00,
+            CadExitMode::CadRx => 0x01,
+            CadExitMode::CadLbt => 0x10,
+        };
+        let [_, t2, t1, t0] = params.timeout.steps().to_be_bytes();
+        self.write(&[
+            opcode::SET_CAD_PARAMS,
+            symbols,
+            params.det_peak,
+            params.det_min,
+            exit_mode,
+            t2,
+            t1,
+            t0,
+        ])
+    }
+
+    /// Starts LoRa channel activity detection
+    ///
+    /// The outcome is reported by the `CAD_DONE` and `CAD_DETECTED` IRQs.
+    pub fn set_cad(&mut self) -> Result<(), Error> {
+        self.write(&[opcode::SET_CAD])
+    }
+
+    /// Returns the instantaneous RSSI in dBm, while receiving
+    pub fn rssi_inst(&mut self) -> Result<i16, Error> {
+        let mut buf = [0];
+        self.read(&[opcode::GET_RSSI_INST], &mut buf)?;
+        Ok(rssi_dbm(buf[0]))
+    }
+
+    /// Listens on `freq` for `duration`, returns `true` if the channel is free
+    ///
+    /// The channel is free if the RSSI stays below `threshold_dbm` until the radio's RX timeout
+    /// of `duration` expires. Returns [`Error::InvalidParameter`] if `duration` is zero or longer
+    /// than 262 s. The packet type and modulation parameters must already be set. The radio is
+    /// left in standby with the RC oscillator.
+    pub fn listen_before_talk(
+        &mut self,
+        freq: Hertz,
+        threshold_dbm: i16,
+        duration: MilliSeconds,
+    ) -> Result<bool, Error> {
+        let timeout = Timeout::from_millis(duration)
+            .filter(|&timeout| timeout != Timeout::DISABLED)
+            .ok_or(Error::InvalidParameter)?;
+
+        self.set_rf_frequency(freq)?;
+        self.set_rx(timeout)?;
+
+        let result = loop {
+            let mut buf = [0];
+            match self.read(&[opcode::GET_RSSI_INST], &mut buf) {
+                // the radio leaves RX once the timeout expires
+                Ok(status) if status.mode() != Some(ChipMode::Rx) => break Ok(true),
+                Ok(_) if rssi_dbm(buf[0]) >= threshold_dbm => break Ok(false),
+                Ok(_) => {}
+                Err(e) => break Err(e),
+            }
+        };
+
+        self.set_standby(StandbyClk::Rc)?;
+        result
+    }
+}
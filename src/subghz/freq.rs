@@ -0,0 +1,118 @@
+//! RF frequency and image calibration
+
+use super::{opcode, Error, SubGhz};
+use crate::time::Hertz;
+
+/// Radio crystal frequency
+const XTAL_FREQ: u64 = 32_000_000;
+
+/// Frequency band with its own image calibration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageBand {
+    /// 430 to 440 MHz
+    Band430,
+    /// 470 to 510 MHz
+    Band470,
+    /// 779 to 787 MHz
+    Band779,
+    /// 863 to 870 MHz
+    Band863,
+    /// 902 to 928 MHz
+    Band902,
+}
+
+impl ImageBand {
+    /// Returns the band `freq` is in, `None` if it isn't in any of them
+    pub fn for_frequency(freq: Hertz) -> Option<Self> {
+        match freq.0 {
+            430_000_000..=440_000_000 => Some(ImageBand::Band430),
+            470_000_000..=510_000_000 => Some(ImageBand::Band470),
+            779_000_000..=787_000_000 => Some(ImageBand::Band779),
+            863_000_000..=870_000_000 => Some(ImageBand::Band863),
+            902_000_000..=928_000_000 => Some(ImageBand::Band902),
+            _ => None,
+        }
+    }
+
+    /// Returns the CalibrateImage parameters, the band limits in steps of 4 MHz
+    pub fn calibration(self) -> [u8; 2] {
+        match self {
+            ImageBand::Band430 => [0x6B, 0x6F],
+            ImageBand::Band470 => [0x75, 0x81],
+            ImageBand::Band779 => [0xC1, 0xC5],
+            ImageBand::Band863 => [0xD7, 0xDB],
+            ImageBand::Band902 => [0xE1, 0xE9],
+        }
+    }
+}
+
+/// Returns the SetRfFrequency register value for `freq`, in steps of 32 MHz / 2^25, rounded to
+/// the nearest step
+pub fn rf_frequency_bits(freq: Hertz) -> u32 {
+    ((((freq.0 as u64) << 25) + XTAL_FREQ / 2) / XTAL_FREQ) as u32
+}
+
+impl SubGhz {
+    /// Sets the RF frequency, from 150 to 960 MHz
+    ///
+    /// When moving to another of the bands in [`ImageBand`], the image calibration is run for
+    /// it first, so the radio must be in standby. Frequencies outside of these bands keep the
+    /// last calibration. Returns [`Error::InvalidParameter`] if the frequency is out of range.
+    pub fn set_rf_frequency(&mut self, freq: Hertz) -> Result<(), Error> {
+        if !(150_000_000..=960_000_000).contains(&freq.0) {
+            return Err(Error::InvalidParameter);
+        }
+
+        if let Some(band) = ImageBand::for_frequency(freq) {
+            if self.image_band != Some(band) {
+                self.calibrate_image(band)?;
+            }
+        }
+
+        let [b3, b2, b1, b0] = rf_frequency_bits(freq).to_be_bytes();
+        self.write(&[opcode::SET_RF_FREQUENCY, b3, b2, b1, b0])
+    }
+
+    /// Runs the image calibration for `band`, the radio must be in standby
+    pub fn calibrate_image(&mut self, band: ImageBand) -> Result<(), Error> {
+        let [f1, f2] = band.calibration();
+        self.write(&[opcode::CALIBRATE_IMAGE, f1, f2])?;
+        self.image_band = Some(band);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rf_frequency_bits, ImageBand};
+    use crate::time::Hertz;
+
+    #[test]
+    fn whole_megahertz_are_exact() {
+        assert_eq!(rf_frequency_bits(Hertz(868_000_000)), 910_163_968);
+        assert_eq!(rf_frequency_bits(Hertz(915_000_000)), 959_447_040);
+        assert_eq!(rf_frequency_bits(Hertz(150_000_000)), 157_286_400);
+        assert_eq!(rf_frequency_bits(Hertz(960_000_000)), 1_006_632_960);
+    }
+
+    #[test]
+    fn frequency_is_rounded_to_the_nearest_step() {
+        // 454216908.8 steps
+        assert_eq!(rf_frequency_bits(Hertz(433_175_000)), 454_216_909);
+        // 910268825.6 steps
+        assert_eq!(rf_frequency_bits(Hertz(868_100_000)), 910_268_826);
+    }
+
+    #[test]
+    fn bands() {
+        let band = |freq| ImageBand::for_frequency(Hertz(freq));
+        assert_eq!(band(433_175_000), Some(ImageBand::Band430));
+        assert_eq!(band(470_000_000), Some(ImageBand::Band470));
+        assert_eq!(band(510_000_000), Some(ImageBand::Band470));
+        assert_eq!(band(780_500_000), Some(ImageBand::Band779));
+        assert_eq!(band(868_100_000), Some(ImageBand::Band863));
+        assert_eq!(band(915_000_000), Some(ImageBand::Band902));
+        assert_eq!(band(450_000_000), None);
+        assert_eq!(band(928_000_001), None);
+    }
+}
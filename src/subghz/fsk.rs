@@ -0,0 +1,527 @@
+//! (G)FSK and (G)MSK modulation and packet parameters
+
+use super::{opcode, Error, SubGhz};
+
+/// Whitening seed registers
+const REG_WHITENING_INIT: u16 = 0x06B8;
+/// CRC seed registers
+const REG_CRC_INIT: u16 = 0x06BC;
+/// CRC polynomial registers
+const REG_CRC_POLY: u16 = 0x06BE;
+/// Sync word registers
+const REG_SYNC_WORD: u16 = 0x06C0;
+/// Node address register
+const REG_NODE_ADDR: u16 = 0x06CD;
+/// Broadcast address register
+const REG_BROADCAST_ADDR: u16 = 0x06CE;
+
+/// Radio crystal frequency, the base of the bit rate and frequency deviation registers
+const XTAL_FREQ: u64 = 32_000_000;
+
+/// Gaussian filter applied to the modulating signal
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PulseShape {
+    /// No filter
+    None,
+    /// Gaussian filter with BT 0.3
+    Bt03,
+    /// Gaussian filter with BT 0.5
+    Bt05,
+    /// Gaussian filter with BT 0.7
+    Bt07,
+    /// Gaussian filter with BT 1
+    Bt1,
+}
+
+impl PulseShape {
+    fn bits(self) -> u8 {
+        match self {
+            PulseShape::None => 0x00,
+            PulseShape::Bt03 => 0x08,
+            PulseShape::Bt05 => 0x09,
+            PulseShape::Bt07 => 0x0A,
+            PulseShape::Bt1 => 0x0B,
+        }
+    }
+}
+
+/// (G)FSK receiver bandwidth
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FskBandwidth {
+    /// 4.8 kHz
+    Bw4,
+    /// 5.8 kHz
+    Bw5,
+    /// 7.3 kHz
+    Bw7,
+    /// 9.7 kHz
+    Bw9,
+    /// 11.7 kHz
+    Bw11,
+    /// 14.6 kHz
+    Bw14,
+    /// 19.5 kHz
+    Bw19,
+    /// 23.4 kHz
+    Bw23,
+    /// 29.3 kHz
+    Bw29,
+    /// 39 kHz
+    Bw39,
+    /// 46.9 kHz
+    Bw46,
+    /// 58.6 kHz
+    Bw58,
+    /// 78.2 kHz
+    Bw78,
+    /// 93.8 kHz
+    Bw93,
+    /// 117.3 kHz
+    Bw117,
+    /// 156.2 kHz
+    Bw156,
+    /// 187.2 kHz
+    Bw187,
+    /// 234.3 kHz
+    Bw234,
+    /// 312 kHz
+    Bw312,
+    /// 373.6 kHz
+    Bw373,
+    /// 467 kHz
+    Bw467,
+}
+
+impl FskBandwidth {
+    const ALL: [FskBandwidth; 21] = [
+        FskBandwidth::Bw4,
+        FskBandwidth::Bw5,
+        FskBandwidth::Bw7,
+        FskBandwidth::Bw9,
+        FskBandwidth::Bw11,
+        FskBandwidth::Bw14,
+        FskBandwidth::Bw19,
+        FskBandwidth::Bw23,
+        FskBandwidth::Bw29,
+        FskBandwidth::Bw39,
+        FskBandwidth::Bw46,
+        FskBandwidth::Bw58,
+        FskBandwidth::Bw78,
+        FskBandwidth::Bw93,
+        FskBandwidth::Bw117,
+        FskBandwidth::Bw156,
+        FskBandwidth::Bw187,
+        FskBandwidth::Bw234,
+        FskBandwidth::Bw312,
+        FskBandwidth::Bw373,
+        FskBandwidth::Bw467,
+    ];
+
+    /// Returns the bandwidth in Hz
+    pub fn hz(self) -> u32 {
+        match self {
+            FskBandwidth::Bw4 => 4_800,
+            FskBandwidth::Bw5 => 5_800,
+            FskBandwidth::Bw7 => 7_300,
+            FskBandwidth::Bw9 => 9_700,
+            FskBandwidth::Bw11 => 11_700,
+            FskBandwidth::Bw14 => 14_600,
+            FskBandwidth::Bw19 => 19_500,
+            FskBandwidth::Bw23 => 23_400,
+            FskBandwidth::Bw29 => 29_300,
+            FskBandwidth::Bw39 => 39_000,
+            FskBandwidth::Bw46 => 46_900,
+            FskBandwidth::Bw58 => 58_600,
+            FskBandwidth::Bw78 => 78_200,
+            FskBandwidth::Bw93 => 93_800,
+            FskBandwidth::Bw117 => 117_300,
+            FskBandwidth::Bw156 => 156_200,
+            FskBandwidth::Bw187 => 187_200,
+            FskBandwidth::Bw234 => 234_300,
+            FskBandwidth::Bw312 => 312_000,
+            FskBandwidth::Bw373 => 373_600,
+            FskBandwidth::Bw467 => 467_000,
+        }
+    }
+
+    /// Returns the narrowest bandwidth that fits a signal with the given bit rate and frequency
+    /// deviation (`bit_rate + 2 * fdev`, Carson's rule), `None` if it is too wide
+    pub fn for_signal(bit_rate: u32, fdev: u32) -> Option<Self> {
+        let occupied = bit_rate as u64 + 2 * fdev as u64;
+        Self::ALL.iter().copied().find(|bw| bw.hz() as u64 >= occupied)
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            FskBandwidth::Bw4 => 0x1F,
+            FskBandwidth::Bw5 => 0x17,
+            FskBandwidth::Bw7 => 0x0F,
+            FskBandwidth::Bw9 => 0x1E,
+            FskBandwidth::Bw11 => 0x16,
+            FskBandwidth::Bw14 => 0x0E,
+            FskBandwidth::Bw19 => 0x1D,
+            FskBandwidth::Bw23 => 0x15,
+            FskBandwidth::Bw29 => 0x0D,
+            FskBandwidth::Bw39 => 0x1C,
+            FskBandwidth::Bw46 => 0x14,
+            FskBandwidth::Bw58 => 0x0C,
+            FskBandwidth::Bw78 => 0x1B,
+            FskBandwidth::Bw93 => 0x13,
+            FskBandwidth::Bw117 => 0x0B,
+            FskBandwidth::Bw156 => 0x1A,
+            FskBandwidth::Bw187 => 0x12,
+            FskBandwidth::Bw234 => 0x0A,
+            FskBandwidth::Bw312 => 0x19,
+            FskBandwidth::Bw373 => 0x11,
+            FskBandwidth::Bw467 => 0x09,
+        }
+    }
+}
+
+/// (G)FSK and (G)MSK modulation parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FskModParams {
+    /// Bit rate in bit/s, from 600 to 300000
+    pub bit_rate: u32,
+    /// Frequency deviation in Hz, up to 200 kHz
+    pub fdev: u32,
+    /// Gaussian filter
+    pub pulse_shape: PulseShape,
+    /// Receiver bandwidth
+    pub bw: FskBandwidth,
+}
+
+impl FskModParams {
+    /// Creates (G)MSK modulation parameters, the frequency deviation is a quarter of the bit rate
+    ///
+    /// Use them with [`PacketType::Msk`](super::PacketType::Msk).
+    pub fn msk(bit_rate: u32, pulse_shape: PulseShape, bw: FskBandwidth) -> Self {
+        FskModParams {
+            bit_rate,
+            fdev: bit_rate / 4,
+            pulse_shape,
+            bw,
+        }
+    }
+
+    /// Returns the bit rate register value, `32 * 32 MHz / bit_rate`, `None` if the bit rate is
+    /// out of range
+    pub fn br_bits(&self) -> Option<u32> {
+        if (600..=300_000).contains(&self.bit_rate) {
+            Some((32 * XTAL_FREQ / self.bit_rate as u64) as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the frequency deviation register value, `fdev * 2^25 / 32 MHz`, `None` if the
+    /// deviation is out of range
+    pub fn fdev_bits(&self) -> Option<u32> {
+        if self.fdev <= 200_000 {
+            Some((((self.fdev as u64) << 25) / XTAL_FREQ) as u32)
+        } else {
+            None
+        }
+    }
+}
+
+/// Preamble detector length
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PreambleDetection {
+    /// Preamble detection disabled
+    Off,
+    /// 8-bit preamble detection
+    Bits8,
+    /// 16-bit preamble detection
+    Bits16,
+    /// 24-bit preamble detection
+    Bits24,
+    /// 32-bit preamble detection
+    Bits32,
+}
+
+/// Sync word of up to 8 bytes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SyncWord {
+    bytes: [u8; 8],
+    len: u8,
+}
+
+impl SyncWord {
+    /// Creates a sync word, `None` if it is longer than 8 bytes
+    pub fn new(sync_word: &[u8]) -> Option<Self> {
+        let mut bytes = [0; 8];
+        bytes.get_mut(..sync_word.len())?.copy_from_slice(sync_word);
+        Some(SyncWord {
+            bytes,
+            len: sync_word.len() as u8,
+        })
+    }
+
+    /// Returns the sync word
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+/// Address filtering
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddrFilter {
+    /// All packets are received
+    Off,
+    /// Only packets for the node address are received
+    Node(u8),
+    /// Only packets for the node or the broadcast address are received
+    NodeAndBroadcast {
+        /// Node address
+        node: u8,
+        /// Broadcast address
+        broadcast: u8,
+    },
+}
+
+/// Packet length
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketLength {
+    /// All packets have the given length, it isn't transmitted
+    Fixed(u8),
+    /// The length is transmitted as the first byte, up to the given maximum
+    Variable(u8),
+}
+
+/// CRC type
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrcType {
+    /// 1-byte CRC
+    Byte1,
+    /// 2-byte CRC
+    Byte2,
+    /// 1-byte CRC, inverted
+    Byte1Inverted,
+    /// 2-byte CRC, inverted
+    Byte2Inverted,
+}
+
+/// CRC configuration
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FskCrc {
+    /// CRC type
+    pub crc_type: CrcType,
+    /// Polynomial
+    pub polynomial: u16,
+    /// Seed
+    pub seed: u16,
+}
+
+impl FskCrc {
+    /// CCITT CRC, as used by many FSK protocols
+    pub const CCITT: FskCrc = FskCrc {
+        crc_type: CrcType::Byte2Inverted,
+        polynomial: 0x1021,
+        seed: 0x1D0F,
+    };
+    /// IBM CRC
+    pub const IBM: FskCrc = FskCrc {
+        crc_type: CrcType::Byte2,
+        polynomial: 0x8005,
+        seed: 0xFFFF,
+    };
+}
+
+/// (G)FSK and (G)MSK packet parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FskPacketParams {
+    /// Preamble length in bits
+    pub preamble_len: u16,
+    /// Preamble detector length
+    pub preamble_detection: PreambleDetection,
+    /// Sync word
+    pub sync_word: SyncWord,
+    /// Address filtering, the address is the first byte after the length
+    pub addr_filter: AddrFilter,
+    /// Packet length
+    pub length: PacketLength,
+    /// CRC, `None` to disable it
+    pub crc: Option<FskCrc>,
+    /// Whitening seed (9-bit), `None` to disable whitening
+    pub whitening: Option<u16>,
+}
+
+impl FskPacketParams {
+    /// Returns the parameters of the SetPacketParams command
+    fn command_bytes(&self) -> [u8; 10] {
+        let [pre_hi, pre_lo] = self.preamble_len.to_be_bytes();
+        let preamble_detection = match self.preamble_detection {
+            PreambleDetection::Off => 0x00,
+            PreambleDetection::Bits8 => 0x04,
+            PreambleDetection::Bits16 => 0x05,
+            PreambleDetection::Bits24 => 0x06,
+            PreambleDetection::Bits32 => 0x07,
+        };
+        let addr_comp = match self.addr_filter {
+            AddrFilter::Off => 0,
+            AddrFilter::Node(_) => 1,
+            AddrFilter::NodeAndBroadcast { .. } => 2,
+        };
+        let (variable, payload_len) = match self.length {
+            PacketLength::Fixed(len) => (0, len),
+            PacketLength::Variable(len) => (1, len),
+        };
+        let crc_type = match self.crc.map(|crc| crc.crc_type) {
+            None => 0x01,
+            Some(CrcType::Byte1) => 0x00,
+            Some(CrcType::Byte2) => 0x02,
+            Some(CrcType::Byte1Inverted) => 0x04,
+            Some(CrcType::Byte2Inverted) => 0x06,
+        };
+
+        [
+            opcode::SET_PACKET_PARAMS,
+            pre_hi,
+            pre_lo,
+            preamble_detection,
+            self.sync_word.len * 8,
+            addr_comp,
+            variable,
+            payload_len,
+            crc_type,
+            self.whitening.is_some() as u8,
+        ]
+    }
+}
+
+impl SubGhz {
+    /// Sets the (G)FSK or (G)MSK modulation parameters
+    ///
+    /// Returns [`Error::InvalidParameter`] if the bit rate or frequency deviation is out of range.
+    pub fn set_fsk_mod_params(&mut self, params: &FskModParams) -> Result<(), Error> {
+        let br = params.br_bits().ok_or(Error::InvalidParameter)?;
+        let fdev = params.fdev_bits().ok_or(Error::InvalidParameter)?;
+        let [_, br2, br1, br0] = br.to_be_bytes();
+        let [_, fdev2, fdev1, fdev0] = fdev.to_be_bytes();
+        self.write(&[
+            opcode::SET_MODULATION_PARAMS,
+            br2,
+            br1,
+            br0,
+            params.pulse_shape.bits(),
+            params.bw.bits(),
+            fdev2,
+            fdev1,
+            fdev0,
+        ])
+    }
+
+    /// Sets the (G)FSK or (G)MSK packet parameters, along with the sync word, addresses, CRC
+    /// and whitening registers
+    ///
+    /// Returns [`Error::InvalidParameter`] if the whitening seed doesn't fit in 9 bits.
+    pub fn set_fsk_packet_params(&mut self, params: &FskPacketParams) -> Result<(), Error> {
+        if params.whitening.map_or(false, |seed| seed > 0x1FF) {
+            return Err(Error::InvalidParameter);
+        }
+
+        self.write(&params.command_bytes())?;
+
+        self.write_register(REG_SYNC_WORD, params.sync_word.as_bytes())?;
+
+        match params.addr_filter {
+            AddrFilter::Off => {}
+            AddrFilter::Node(node) => self.write_register(REG_NODE_ADDR, &[node])?,
+            AddrFilter::NodeAndBroadcast { node, broadcast } => {
+                self.write_register(REG_NODE_ADDR, &[node])?;
+                self.write_register(REG_BROADCAST_ADDR, &[broadcast])?;
+            }
+        }
+
+        if let Some(crc) = params.crc {
+            self.write_register(REG_CRC_INIT, &crc.seed.to_be_bytes())?;
+            self.write_register(REG_CRC_POLY, &crc.polynomial.to_be_bytes())?;
+        }
+
+        if let Some(seed) = params.whitening {
+            // the most significant bit of the seed shares its register with other settings
+            let mut msb = [0];
+            self.read_register(REG_WHITENING_INIT, &mut msb)?;
+            let [seed_hi, seed_lo] = seed.to_be_bytes();
+            self.write_register(REG_WHITENING_INIT, &[(msb[0] & !1) | seed_hi, seed_lo])?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(bit_rate: u32, fdev: u32) -> FskModParams {
+        FskModParams {
+            bit_rate,
+            fdev,
+            pulse_shape: PulseShape::Bt05,
+            bw: FskBandwidth::Bw117,
+        }
+    }
+
+    #[test]
+    fn bit_rate_register() {
+        assert_eq!(params(50_000, 25_000).br_bits(), Some(20_480));
+        assert_eq!(params(4_800, 25_000).br_bits(), Some(213_333));
+        assert_eq!(params(600, 25_000).br_bits(), Some(1_706_666));
+        assert_eq!(params(300_000, 25_000).br_bits(), Some(3_413));
+    }
+
+    #[test]
+    fn bit_rate_out_of_range() {
+        assert_eq!(params(599, 25_000).br_bits(), None);
+        assert_eq!(params(300_001, 25_000).br_bits(), None);
+    }
+
+    #[test]
+    fn fdev_register() {
+        assert_eq!(params(50_000, 25_000).fdev_bits(), Some(26_214));
+        assert_eq!(params(50_000, 0).fdev_bits(), Some(0));
+        assert_eq!(params(50_000, 200_000).fdev_bits(), Some(209_715));
+        assert_eq!(params(50_000, 200_001).fdev_bits(), None);
+    }
+
+    #[test]
+    fn msk_deviation_is_a_quarter_of_the_bit_rate() {
+        let msk = FskModParams::msk(4_800, PulseShape::Bt05, FskBandwidth::Bw9);
+        assert_eq!(msk.fdev, 1_200);
+    }
+
+    #[test]
+    fn bandwidth_fits_signal() {
+        assert_eq!(FskBandwidth::for_signal(50_000, 25_000), Some(FskBandwidth::Bw117));
+        assert_eq!(FskBandwidth::for_signal(4_800, 0), Some(FskBandwidth::Bw4));
+        assert_eq!(FskBandwidth::for_signal(4_800, 1), Some(FskBandwidth::Bw5));
+        assert_eq!(FskBandwidth::for_signal(300_000, 100_000), None);
+    }
+
+    #[test]
+    fn sync_word_is_at_most_8_bytes() {
+        let sync_word = SyncWord::new(&[0xC1, 0x94, 0xC1]).unwrap();
+        assert_eq!(sync_word.as_bytes(), [0xC1, 0x94, 0xC1]);
+        assert!(SyncWord::new(&[0; 8]).is_some());
+        assert!(SyncWord::new(&[0; 9]).is_none());
+    }
+
+    #[test]
+    fn packet_params_command() {
+        let params = FskPacketParams {
+            preamble_len: 40,
+            preamble_detection: PreambleDetection::Bits16,
+            sync_word: SyncWord::new(&[0xC1, 0x94, 0xC1]).unwrap(),
+            addr_filter: AddrFilter::Off,
+            length: PacketLength::Variable(255),
+            crc: Some(FskCrc::CCITT),
+            whitening: Some(0x01FF),
+        };
+        assert_eq!(
+            params.command_bytes(),
+            [opcode::SET_PACKET_PARAMS, 0, 40, 0x05, 24, 0, 1, 255, 0x06, 1]
+        );
+    }
+}
@@ -0,0 +1,177 @@
+//! Radio interrupts
+//!
+//! The radio raises the SUBGHZ_Radio interrupt, named `RADIO_BUSY` in the PAC, for the IRQs
+//! enabled with [`SubGhz::set_dio_irq_params`]. The interrupt stays pending until the IRQs are
+//! cleared with [`SubGhz::clear_irq_status`], so its handler has to read and clear them.
+
+use core::ops::{BitOr, BitOrAssign};
+
+use cortex_m::interrupt;
+use cortex_m::peripheral::NVIC;
+
+use super::{opcode, Error, SubGhz};
+use crate::pac::{Interrupt, EXTI};
+
+/// Bit of the radio interrupt's EXTI line 44 in IMR2
+const EXTI_LINE: u32 = 44 - 32;
+
+/// Set of radio IRQs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Irq(u16);
+
+impl Irq {
+    /// No IRQ
+    pub const NONE: Irq = Irq(0);
+    /// Packet transmitted
+    pub const TX_DONE: Irq = Irq(1 << 0);
+    /// Packet received
+    pub const RX_DONE: Irq = Irq(1 << 1);
+    /// Preamble detected
+    pub const PREAMBLE_DETECTED: Irq = Irq(1 << 2);
+    /// Valid sync word detected
+    pub const SYNC_WORD_VALID: Irq = Irq(1 << 3);
+    /// Valid LoRa header received
+    pub const HEADER_VALID: Irq = Irq(1 << 4);
+    /// LoRa header CRC error
+    pub const HEADER_ERR: Irq = Irq(1 << 5);
+    /// Payload CRC error
+    pub const CRC_ERR: Irq = Irq(1 << 6);
+    /// Channel activity detection done
+    pub const CAD_DONE: Irq = Irq(1 << 7);
+    /// Channel activity detected
+    pub const CAD_DETECTED: Irq = Irq(1 << 8);
+    /// RX or TX timeout
+    pub const TIMEOUT: Irq = Irq(1 << 9);
+    /// All IRQs
+    pub const ALL: Irq = Irq(0x03FF);
+
+    /// Creates a set from the IRQ status bits, unknown bits are dropped
+    pub const fn from_bits(bits: u16) -> Self {
+        Irq(bits & Self::ALL.0)
+    }
+
+    /// Returns the IRQ status bits
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    /// Returns `true` if the set is empty
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if all IRQs in `other` are in the set
+    pub const fn contains(self, other: Irq) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if any IRQ in `other` is in the set
+    pub const fn intersects(self, other: Irq) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl BitOr for Irq {
+    type Output = Irq;
+
+    fn bitor(self, rhs: Irq) -> Irq {
+        Irq(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Irq {
+    fn bitor_assign(&mut self, rhs: Irq) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl SubGhz {
+    /// Enables the IRQs in `irq_mask` and routes them to the radio's internal IRQ lines
+    ///
+    /// The SUBGHZ_Radio interrupt is raised for an enabled IRQ routed to any of the lines.
+    pub fn set_dio_irq_params(
+        &mut self,
+        irq_mask: Irq,
+        line1: Irq,
+        line2: Irq,
+        line3: Irq,
+    ) -> Result<(), Error> {
+        let [m1, m0] = irq_mask.bits().to_be_bytes();
+        let [a1, a0] = line1.bits().to_be_bytes();
+        let [b1, b0] = line2.bits().to_be_bytes();
+        let [c1, c0] = line3.bits().to_be_bytes();
+        self.write(&[opcode::CFG_DIO_IRQ, m1, m0, a1, a0, b1, b0, c1, c0])
+    }
+
+    /// Enables the IRQs in `irq` and routes them to the SUBGHZ_Radio interrupt
+    pub fn enable_irqs(&mut self, irq: Irq) -> Result<(), Error> {
+        self.set_dio_irq_params(irq, irq, Irq::NONE, Irq::NONE)
+    }
+
+    /// Returns the pending IRQs
+    pub fn irq_status(&mut self) -> Result<Irq, Error> {
+        let mut buf = [0; 2];
+        self.read(&[opcode::GET_IRQ_STATUS], &mut buf)?;
+        Ok(Irq::from_bits(u16::from_be_bytes(buf)))
+    }
+
+    /// Clears the IRQs in `irq`
+    pub fn clear_irq_status(&mut self, irq: Irq) -> Result<(), Error> {
+        let [hi, lo] = irq.bits().to_be_bytes();
+        self.write(&[opcode::CLR_IRQ_STATUS, hi, lo])
+    }
+}
+
+/// Unmasks the SUBGHZ_Radio interrupt in the NVIC and its EXTI line, so it also wakes the CPU
+/// up from stop modes
+///
+/// # Safety
+///
+/// This can break mask-based critical sections, see [`NVIC::unmask`].
+pub unsafe fn unmask_irq() {
+    interrupt::free(|_| {
+        // NOTE(unsafe) read-modify-write in a critical section, the PAC doesn't know this line
+        let exti = &*EXTI::ptr();
+        exti.imr2.modify(|r, w| w.bits(r.bits() | 1 << EXTI_LINE));
+    });
+    NVIC::unmask(Interrupt::RADIO_BUSY);
+}
+
+/// Masks the SUBGHZ_Radio interrupt in the NVIC and its EXTI line
+pub fn mask_irq() {
+    NVIC::mask(Interrupt::RADIO_BUSY);
+    interrupt::free(|_| {
+        // NOTE(unsafe) see note above
+        let exti = unsafe { &*EXTI::ptr() };
+        exti.imr2.modify(|r, w| unsafe { w.bits(r.bits() & !(1 << EXTI_LINE)) });
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Irq;
+
+    #[test]
+    fn irqs_are_combined() {
+        let mut irq = Irq::TX_DONE | Irq::RX_DONE;
+        assert_eq!(irq.bits(), 0x0003);
+        irq |= Irq::TIMEOUT;
+        assert_eq!(irq.bits(), 0x0203);
+        assert!(irq.contains(Irq::TX_DONE | Irq::TIMEOUT));
+        assert!(!irq.contains(Irq::TX_DONE | Irq::CRC_ERR));
+        assert!(irq.intersects(Irq::TX_DONE | Irq::CRC_ERR));
+    }
+
+    #[test]
+    fn status_is_decoded() {
+        let irq = Irq::from_bits(0x0142);
+        assert_eq!(irq, Irq::RX_DONE | Irq::CRC_ERR | Irq::CAD_DETECTED);
+        assert!(Irq::from_bits(0).is_empty());
+    }
+
+    #[test]
+    fn unknown_bits_are_dropped() {
+        assert_eq!(Irq::from_bits(0xFFFF), Irq::ALL);
+        assert!(Irq::from_bits(0xFC00).is_empty());
+    }
+}
@@ -0,0 +1,260 @@
+//! LoRa modulation and packet parameters
+
+use super::{opcode, Error, SubGhz};
+
+/// Sync word registers
+const REG_LORA_SYNC_WORD: u16 = 0x0740;
+/// IQ polarity register, see [`SubGhz::set_lora_packet_params`]
+const REG_IQ_POLARITY: u16 = 0x0736;
+
+/// Spreading factor
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SpreadingFactor {
+    /// 32 chips per symbol
+    Sf5 = 5,
+    /// 64 chips per symbol
+    Sf6 = 6,
+    /// 128 chips per symbol
+    Sf7 = 7,
+    /// 256 chips per symbol
+    Sf8 = 8,
+    /// 512 chips per symbol
+    Sf9 = 9,
+    /// 1024 chips per symbol
+    Sf10 = 10,
+    /// 2048 chips per symbol
+    Sf11 = 11,
+    /// 4096 chips per symbol
+    Sf12 = 12,
+}
+
+/// LoRa bandwidth
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoRaBandwidth {
+    /// 7.81 kHz
+    Bw7,
+    /// 10.42 kHz
+    Bw10,
+    /// 15.63 kHz
+    Bw15,
+    /// 20.83 kHz
+    Bw20,
+    /// 31.25 kHz
+    Bw31,
+    /// 41.67 kHz
+    Bw41,
+    /// 62.5 kHz
+    Bw62,
+    /// 125 kHz
+    Bw125,
+    /// 250 kHz
+    Bw250,
+    /// 500 kHz
+    Bw500,
+}
+
+impl LoRaBandwidth {
+    /// Returns the bandwidth in Hz, rounded down
+    pub fn hz(self) -> u32 {
+        match self {
+            LoRaBandwidth::Bw7 => 7_812,
+            LoRaBandwidth::Bw10 => 10_416,
+            LoRaBandwidth::Bw15 => 15_625,
+            LoRaBandwidth::Bw20 => 20_833,
+            LoRaBandwidth::Bw31 => 31_250,
+            LoRaBandwidth::Bw41 => 41_666,
+            LoRaBandwidth::Bw62 => 62_500,
+            LoRaBandwidth::Bw125 => 125_000,
+            LoRaBandwidth::Bw250 => 250_000,
+            LoRaBandwidth::Bw500 => 500_000,
+        }
+    }
+
+    fn bits(self) -> u8 {
+        match self {
+            LoRaBandwidth::Bw7 => 0x00,
+            LoRaBandwidth::Bw10 => 0x08,
+            LoRaBandwidth::Bw15 => 0x01,
+            LoRaBandwidth::Bw20 => 0x09,
+            LoRaBandwidth::Bw31 => 0x02,
+            LoRaBandwidth::Bw41 => 0x0A,
+            LoRaBandwidth::Bw62 => 0x03,
+            LoRaBandwidth::Bw125 => 0x04,
+            LoRaBandwidth::Bw250 => 0x05,
+            LoRaBandwidth::Bw500 => 0x06,
+        }
+    }
+}
+
+/// Forward error correction coding rate
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CodingRate {
+    /// 4/5
+    Cr45 = 1,
+    /// 4/6
+    Cr46 = 2,
+    /// 4/7
+    Cr47 = 3,
+    /// 4/8
+    Cr48 = 4,
+}
+
+/// LoRa modulation parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoRaModParams {
+    /// Spreading factor
+    pub sf: SpreadingFactor,
+    /// Bandwidth
+    pub bw: LoRaBandwidth,
+    /// Coding rate
+    pub cr: CodingRate,
+    /// Low data-rate optimization, `None` to enable it when the symbol time exceeds 16.38 ms
+    pub ldro: Option<bool>,
+}
+
+impl LoRaModParams {
+    /// Creates modulation parameters with automatic low data-rate optimization
+    pub fn new(sf: SpreadingFactor, bw: LoRaBandwidth, cr: CodingRate) -> Self {
+        LoRaModParams {
+            sf,
+            bw,
+            cr,
+            ldro: None,
+        }
+    }
+
+    /// Returns the symbol time in µs
+    pub fn symbol_time_us(&self) -> u32 {
+        ((1_000_000u64 << self.sf as u8) / self.bw.hz() as u64) as u32
+    }
+
+    /// Returns `true` if low data-rate optimization is used
+    pub fn ldro_enabled(&self) -> bool {
+        self.ldro.unwrap_or_else(|| self.symbol_time_us() > 16_380)
+    }
+}
+
+/// LoRa header type
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HeaderType {
+    /// Explicit header carrying the payload length, coding rate and CRC presence
+    Explicit,
+    /// Implicit header, both sides must agree on the payload length, coding rate and CRC
+    Implicit,
+}
+
+/// LoRa packet parameters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoRaPacketParams {
+    /// Preamble length in symbols
+    pub preamble_len: u16,
+    /// Header type
+    pub header: HeaderType,
+    /// Payload length in bytes, the maximum length to receive with an explicit header
+    pub payload_len: u8,
+    /// Append a CRC to the payload
+    pub crc: bool,
+    /// Invert the IQ signals, as used for LoRaWAN downlinks
+    pub invert_iq: bool,
+}
+
+/// LoRa sync word
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LoRaSyncWord {
+    /// Public network, e.g. LoRaWAN
+    Public,
+    /// Private network
+    Private,
+}
+
+impl LoRaSyncWord {
+    /// Returns the value of the sync word registers
+    pub fn bits(self) -> u16 {
+        match self {
+            LoRaSyncWord::Public => 0x3444,
+            LoRaSyncWord::Private => 0x1424,
+        }
+    }
+}
+
+impl SubGhz {
+    /// Sets the LoRa modulation parameters
+    pub fn set_lora_mod_params(&mut self, params: &LoRaModParams) -> Result<(), Error> {
+        self.write(&[
+            opcode::SET_MODULATION_PARAMS,
+            params.sf as u8,
+            params.bw.bits(),
+            params.cr as u8,
+            params.ldro_enabled() as u8,
+        ])
+    }
+
+    /// Sets the LoRa packet parameters
+    pub fn set_lora_packet_params(&mut self, params: &LoRaPacketParams) -> Result<(), Error> {
+        let [pre_hi, pre_lo] = params.preamble_len.to_be_bytes();
+        self.write(&[
+            opcode::SET_PACKET_PARAMS,
+            pre_hi,
+            pre_lo,
+            (params.header == HeaderType::Implicit) as u8,
+            params.payload_len,
+            params.crc as u8,
+            params.invert_iq as u8,
+        ])?;
+
+        // the IQ polarity register has to match the IQ setting, otherwise packets with inverted
+        // IQ are lost
+        let mut iq = [0];
+        self.read_register(REG_IQ_POLARITY, &mut iq)?;
+        let iq = if params.invert_iq {
+            iq[0] & !(1 << 2)
+        } else {
+            iq[0] | 1 << 2
+        };
+        self.write_register(REG_IQ_POLARITY, &[iq])
+    }
+
+    /// Sets the LoRa sync word
+    pub fn set_lora_sync_word(&mut self, sync_word: LoRaSyncWord) -> Result<(), Error> {
+        self.write_register(REG_LORA_SYNC_WORD, &sync_word.bits().to_be_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CodingRate, LoRaBandwidth, LoRaModParams, SpreadingFactor};
+
+    #[test]
+    fn symbol_time() {
+        let params =
+            LoRaModParams::new(SpreadingFactor::Sf7, LoRaBandwidth::Bw125, CodingRate::Cr45);
+        assert_eq!(params.symbol_time_us(), 1_024);
+        let params =
+            LoRaModParams::new(SpreadingFactor::Sf12, LoRaBandwidth::Bw500, CodingRate::Cr48);
+        assert_eq!(params.symbol_time_us(), 8_192);
+    }
+
+    #[test]
+    fn ldro_is_selected_above_16_38_ms() {
+        let ldro = |sf, bw| LoRaModParams::new(sf, bw, CodingRate::Cr45).ldro_enabled();
+        assert!(!ldro(SpreadingFactor::Sf10, LoRaBandwidth::Bw125));
+        assert!(ldro(SpreadingFactor::Sf11, LoRaBandwidth::Bw125));
+        assert!(ldro(SpreadingFactor::Sf12, LoRaBandwidth::Bw125));
+        assert!(ldro(SpreadingFactor::Sf12, LoRaBandwidth::Bw250));
+        assert!(!ldro(SpreadingFactor::Sf12, LoRaBandwidth::Bw500));
+        assert!(ldro(SpreadingFactor::Sf7, LoRaBandwidth::Bw7));
+        assert!(!ldro(SpreadingFactor::Sf6, LoRaBandwidth::Bw7));
+    }
+
+    #[test]
+    fn ldro_can_be_forced() {
+        let mut params =
+            LoRaModParams::new(SpreadingFactor::Sf7, LoRaBandwidth::Bw125, CodingRate::Cr45);
+        params.ldro = Some(true);
+        assert!(params.ldro_enabled());
+        let mut params =
+            LoRaModParams::new(SpreadingFactor::Sf12, LoRaBandwidth::Bw125, CodingRate::Cr45);
+        params.ldro = Some(false);
+        assert!(!params.ldro_enabled());
+    }
+}
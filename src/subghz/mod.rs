@@ -0,0 +1,573 @@
+//! Sub-GHz radio
+//!
+//! The radio is controlled with commands sent over SUBGHZSPI. Its NSS is not a GPIO but the
+//! `NSS` bit of PWR `SUBGHZSPICR`, and its BUSY signal is read from PWR `SR2`. [`SubGhz`] owns
+//! SUBGHZSPI and takes care of both: it waits until the radio is ready before every command and
+//! wakes it up first if it was put to sleep.
+
+use cortex_m::interrupt;
+
+use crate::hal::blocking::spi::{Transfer, Write};
+use crate::pac::{PWR, RCC};
+use crate::rcc::{Clocks, APB3};
+use crate::spi::{self, Spi, SUBGHZSPI};
+use crate::time::{MicroSeconds, MilliSeconds};
+
+pub use self::bpsk::BpskBitRate;
+pub use self::cad::{CadExitMode, CadParams, CadSymbols};
+pub use self::freq::{rf_frequency_bits, ImageBand};
+pub use self::fsk::{AddrFilter, CrcType, FskBandwidth, FskCrc, FskModParams, FskPacketParams};
+pub use self::fsk::{PacketLength, PreambleDetection, PulseShape, SyncWord};
+pub use self::irq::{mask_irq, unmask_irq, Irq};
+pub use self::lora::{CodingRate, HeaderType, LoRaBandwidth, LoRaModParams, LoRaPacketParams};
+pub use self::lora::{LoRaSyncWord, SpreadingFactor};
+pub use self::pa::{PaAvailability, PaConfig, PaSel, RampTime};
+pub use self::status::{FskPacketStatus, FskRxStatus, LoRaPacketStatus, RxBufferStatus, Stats};
+
+mod bpsk;
+mod cad;
+mod freq;
+mod fsk;
+mod irq;
+mod lora;
+mod pa;
+mod status;
+
+/// Radio error
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum Error {
+    /// SUBGHZSPI error
+    Spi(spi::Error),
+    /// A parameter is out of the range supported by the radio
+    InvalidParameter,
+}
+
+impl From<spi::Error> for Error {
+    fn from(error: spi::Error) -> Self {
+        Error::Spi(error)
+    }
+}
+
+mod opcode {
+    pub const SET_SLEEP: u8 = 0x84;
+    pub const SET_STANDBY: u8 = 0x80;
+    pub const SET_FS: u8 = 0xC1;
+    pub const SET_TX: u8 = 0x83;
+    pub const SET_RX: u8 = 0x82;
+    pub const WRITE_BUFFER: u8 = 0x0E;
+    pub const READ_BUFFER: u8 = 0x1E;
+    pub const WRITE_REGISTER: u8 = 0x0D;
+    pub const READ_REGISTER: u8 = 0x1D;
+    pub const GET_STATUS: u8 = 0xC0;
+    pub const GET_DEVICE_ERRORS: u8 = 0x17;
+    pub const CLEAR_DEVICE_ERRORS: u8 = 0x07;
+    pub const SET_PACKET_TYPE: u8 = 0x8A;
+    pub const SET_MODULATION_PARAMS: u8 = 0x8B;
+    pub const SET_PACKET_PARAMS: u8 = 0x8C;
+    pub const SET_PA_CONFIG: u8 = 0x95;
+    pub const SET_TX_PARAMS: u8 = 0x8E;
+    pub const CFG_DIO_IRQ: u8 = 0x08;
+    pub const GET_IRQ_STATUS: u8 = 0x12;
+    pub const CLR_IRQ_STATUS: u8 = 0x02;
+    pub const SET_RF_FREQUENCY: u8 = 0x86;
+    pub const SET_CAD_PARAMS: u8 = 0x88;
+    pub const SET_CAD: u8 = 0xC5;
+    pub const GET_RSSI_INST: u8 = 0x15;
+    pub const CALIBRATE_IMAGE: u8 = 0x98;
+    pub const SET_RX_DUTY_CYCLE: u8 = 0x94;
+    pub const SET_STOP_RX_TIMER_ON_PREAMBLE: u8 = 0x9F;
+    pub const GET_RX_BUFFER_STATUS: u8 = 0x13;
+    pub const GET_PACKET_STATUS: u8 = 0x14;
+    pub const GET_STATS: u8 = 0x10;
+    pub const RESET_STATS: u8 = 0x00;
+}
+
+/// Packet type, selecting the modem
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketType {
+    /// (G)FSK
+    Fsk,
+    /// LoRa
+    LoRa,
+    /// BPSK, transmit only
+    Bpsk,
+    /// (G)MSK
+    Msk,
+}
+
+/// Clock used in standby mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StandbyClk {
+    /// 13 MHz RC oscillator
+    Rc,
+    /// 32 MHz HSE32 oscillator
+    Hse,
+}
+
+/// Sleep mode configuration
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SleepCfg {
+    /// Keep the configuration in retention memory, so it is restored on wake-up (warm start)
+    pub warm_start: bool,
+    /// Wake up on the RTC timer event
+    pub rtc_wakeup: bool,
+}
+
+impl SleepCfg {
+    fn bits(self) -> u8 {
+        (self.warm_start as u8) << 2 | self.rtc_wakeup as u8
+    }
+}
+
+/// TX and RX timeout, in steps of 15.625 µs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeout(u32);
+
+impl Timeout {
+    /// No timeout, the radio stays in TX until the packet is sent or in RX until one is received
+    pub const DISABLED: Timeout = Timeout(0);
+    /// In RX, keep receiving packets until the radio is switched to another mode
+    pub const CONTINUOUS: Timeout = Timeout(0xFF_FFFF);
+
+    /// Creates a timeout of `duration`, rounded to the nearest step, `None` if it doesn't fit in
+    /// 24 bits (about 262 s)
+    pub fn from_micros(duration: MicroSeconds) -> Option<Self> {
+        let steps = (duration.0 as u64 * 64 + 500) / 1_000;
+        if steps > 0xFF_FFFF {
+            None
+        } else {
+            Some(Timeout(steps as u32))
+        }
+    }
+
+    /// Creates a timeout of `duration`, `None` if it doesn't fit in 24 bits (about 262 s)
+    pub fn from_millis(duration: MilliSeconds) -> Option<Self> {
+        let steps = duration.0 as u64 * 64;
+        if steps > 0xFF_FFFF {
+            None
+        } else {
+            Some(Timeout(steps as u32))
+        }
+    }
+
+    /// Creates a timeout of `steps` times 15.625 µs, `None` if it doesn't fit in 24 bits
+    pub const fn from_steps(steps: u32) -> Option<Self> {
+        if steps > 0xFF_FFFF {
+            None
+        } else {
+            Some(Timeout(steps))
+        }
+    }
+
+    /// Returns the timeout in steps of 15.625 µs
+    pub const fn steps(self) -> u32 {
+        self.0
+    }
+
+    fn to_be_bytes(self) -> [u8; 3] {
+        let [_, b2, b1, b0] = self.0.to_be_bytes();
+        [b2, b1, b0]
+    }
+}
+
+/// Radio operating mode, as reported in [`Status`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChipMode {
+    /// Standby with the RC oscillator
+    StandbyRc,
+    /// Standby with the HSE32 oscillator
+    StandbyHse,
+    /// Frequency synthesis
+    Fs,
+    /// Receive
+    Rx,
+    /// Transmit
+    Tx,
+}
+
+/// Outcome of the last command, as reported in [`Status`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CmdStatus {
+    /// Data is available to the host
+    DataAvailable,
+    /// The command timed out
+    Timeout,
+    /// The command could not be processed, e.g. because of an invalid opcode or parameter
+    ProcessingError,
+    /// The command was processed but could not be executed
+    ExecutionFailure,
+    /// The packet was transmitted
+    TxDone,
+}
+
+/// Radio status byte
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Status(u8);
+
+impl Status {
+    /// Wraps a status byte read from the radio
+    pub const fn from_raw(raw: u8) -> Self {
+        Status(raw)
+    }
+
+    /// Returns the status byte
+    pub const fn raw(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the operating mode, `None` for reserved values
+    pub fn mode(self) -> Option<ChipMode> {
+        match (self.0 >> 4) & 0b111 {
+            0x2 => Some(ChipMode::StandbyRc),
+            0x3 => Some(ChipMode::StandbyHse),
+            0x4 => Some(ChipMode::Fs),
+            0x5 => Some(ChipMode::Rx),
+            0x6 => Some(ChipMode::Tx),
+            _ => None,
+        }
+    }
+
+    /// Returns the outcome of the last command, `None` if there is nothing to report
+    pub fn cmd(self) -> Option<CmdStatus> {
+        match (self.0 >> 1) & 0b111 {
+            0x2 => Some(CmdStatus::DataAvailable),
+            0x3 => Some(CmdStatus::Timeout),
+            0x4 => Some(CmdStatus::ProcessingError),
+            0x5 => Some(CmdStatus::ExecutionFailure),
+            0x6 => Some(CmdStatus::TxDone),
+            _ => None,
+        }
+    }
+}
+
+/// Errors reported by the radio with GetDeviceErrors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpError(u16);
+
+impl OpError {
+    /// RC 64 kHz oscillator calibration failed
+    pub const RC64K_CALIB: OpError = OpError(1 << 0);
+    /// RC 13 MHz oscillator calibration failed
+    pub const RC13M_CALIB: OpError = OpError(1 << 1);
+    /// PLL calibration failed
+    pub const PLL_CALIB: OpError = OpError(1 << 2);
+    /// ADC calibration failed
+    pub const ADC_CALIB: OpError = OpError(1 << 3);
+    /// Image calibration failed
+    pub const IMG_CALIB: OpError = OpError(1 << 4);
+    /// HSE32 oscillator failed to start
+    pub const XOSC_START: OpError = OpError(1 << 5);
+    /// PLL failed to lock
+    pub const PLL_LOCK: OpError = OpError(1 << 6);
+    /// PA ramping failed
+    pub const PA_RAMP: OpError = OpError(1 << 8);
+
+    /// Wraps the error flags read from the radio
+    pub const fn from_bits(bits: u16) -> Self {
+        OpError(bits)
+    }
+
+    /// Returns the error flags
+    pub const fn bits(self) -> u16 {
+        self.0
+    }
+
+    /// Returns `true` if no error is flagged
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns `true` if all errors in `other` are flagged
+    pub const fn contains(self, other: OpError) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// Sub-GHz radio
+pub struct SubGhz {
+    spi: Spi<SUBGHZSPI, ()>,
+    pas: PaAvailability,
+    sleeping: bool,
+    image_band: Option<ImageBand>,
+}
+
+impl SubGhz {
+    /// Configures SUBGHZSPI and takes the radio out of reset
+    ///
+    /// The radio starts in standby mode with the RC oscillator. `pas` are the PAs the board
+    /// connects to the antenna, the other one is rejected by the PA configuration.
+    pub fn new(
+        spi: SUBGHZSPI,
+        pas: PaAvailability,
+        clocks: Clocks,
+        apb: &mut APB3,
+    ) -> Result<Self, Error> {
+        let spi = Spi::subghz(spi, clocks, apb)?;
+        let mut radio = SubGhz {
+            spi,
+            pas,
+            sleeping: false,
+            image_band: None,
+        };
+        radio.deselect();
+        radio.set_reset(false);
+        radio.wait_ready();
+        Ok(radio)
+    }
+
+    /// Resets the radio, it restarts in standby mode with the RC oscillator
+    pub fn reset(&mut self) {
+        self.set_reset(true);
+        self.set_reset(false);
+        self.sleeping = false;
+        self.image_band = None;
+        self.wait_ready();
+    }
+
+    /// Puts the radio in sleep mode
+    ///
+    /// It is woken up again by the next command.
+    pub fn set_sleep(&mut self, cfg: SleepCfg) -> Result<(), Error> {
+        self.write(&[opcode::SET_SLEEP, cfg.bits()])?;
+        self.sleeping = true;
+        if !cfg.warm_start {
+            self.image_band = None;
+        }
+        Ok(())
+    }
+
+    /// Puts the radio in standby mode
+    pub fn set_standby(&mut self, clk: StandbyClk) -> Result<(), Error> {
+        self.write(&[opcode::SET_STANDBY, (clk == StandbyClk::Hse) as u8])
+    }
+
+    /// Puts the radio in frequency synthesis mode, with the PLL locked to the RF frequency
+    pub fn set_fs(&mut self) -> Result<(), Error> {
+        self.write(&[opcode::SET_FS])
+    }
+
+    /// Starts transmitting the packet in the data buffer
+    pub fn set_tx(&mut self, timeout: Timeout) -> Result<(), Error> {
+        let [t2, t1, t0] = timeout.to_be_bytes();
+        self.write(&[opcode::SET_TX, t2, t1, t0])
+    }
+
+    /// Starts receiving
+    pub fn set_rx(&mut self, timeout: Timeout) -> Result<(), Error> {
+        let [t2, t1, t0] = timeout.to_be_bytes();
+        self.write(&[opcode::SET_RX, t2, t1, t0])
+    }
+
+    /// Selects the modem, this must be done in standby mode before setting the modulation and
+    /// packet parameters
+    pub fn set_packet_type(&mut self, packet_type: PacketType) -> Result<(), Error> {
+        let bits = match packet_type {
+            PacketType::Fsk => 0x00,
+            PacketType::LoRa => 0x01,
+            PacketType::Bpsk => 0x02,
+            PacketType::Msk => 0x03,
+        };
+        self.write(&[opcode::SET_PACKET_TYPE, bits])
+    }
+
+    /// Starts receiving in duty cycle mode
+    ///
+    /// The radio alternates between receiving for `rx_period` and sleeping with a warm start for
+    /// `sleep_period` until a packet is detected, then keeps receiving it as with
+    /// [`set_rx`](Self::set_rx). The next command ends the duty cycle. Returns
+    /// [`Error::InvalidParameter`] if a period is zero or longer than 24 bits of 15.625 µs steps.
+    pub fn set_rx_duty_cycle(
+        &mut self,
+        rx_period: MicroSeconds,
+        sleep_period: MicroSeconds,
+    ) -> Result<(), Error> {
+        let rx = Timeout::from_micros(rx_period).ok_or(Error::InvalidParameter)?;
+        let sleep = Timeout::from_micros(sleep_period).ok_or(Error::InvalidParameter)?;
+        if rx == Timeout::DISABLED || sleep == Timeout::DISABLED {
+            return Err(Error::InvalidParameter);
+        }
+
+        let [r2, r1, r0] = rx.to_be_bytes();
+        let [s2, s1, s0] = sleep.to_be_bytes();
+        self.write(&[opcode::SET_RX_DUTY_CYCLE, r2, r1, r0, s2, s1, s0])?;
+        // BUSY is high while the radio sleeps, it has to be woken up like from sleep mode
+        self.sleeping = true;
+        Ok(())
+    }
+
+    /// Selects whether the RX timeout is stopped by a detected preamble, instead of by a valid
+    /// sync word or LoRa header
+    pub fn set_stop_rx_timer_on_preamble(&mut self, on_preamble: bool) -> Result<(), Error> {
+        self.write(&[opcode::SET_STOP_RX_TIMER_ON_PREAMBLE, on_preamble as u8])
+    }
+
+    /// Writes `data` to the data buffer, starting at `offset`
+    pub fn write_buffer(&mut self, offset: u8, data: &[u8]) -> Result<(), Error> {
+        self.write_with_data(&[opcode::WRITE_BUFFER, offset], data)
+    }
+
+    /// Reads the data buffer into `buf`, starting at `offset`
+    pub fn read_buffer(&mut self, offset: u8, buf: &mut [u8]) -> Result<Status, Error> {
+        self.read(&[opcode::READ_BUFFER, offset], buf)
+    }
+
+    /// Writes `data` to consecutive registers, starting at `address`
+    pub fn write_register(&mut self, address: u16, data: &[u8]) -> Result<(), Error> {
+        let [hi, lo] = address.to_be_bytes();
+        self.write_with_data(&[opcode::WRITE_REGISTER, hi, lo], data)
+    }
+
+    /// Reads consecutive registers into `buf`, starting at `address`
+    pub fn read_register(&mut self, address: u16, buf: &mut [u8]) -> Result<(), Error> {
+        let [hi, lo] = address.to_be_bytes();
+        self.read(&[opcode::READ_REGISTER, hi, lo], buf)?;
+        Ok(())
+    }
+
+    /// Returns the radio status
+    pub fn status(&mut self) -> Result<Status, Error> {
+        self.read(&[opcode::GET_STATUS], &mut [])
+    }
+
+    /// Returns the errors flagged by the radio
+    pub fn device_errors(&mut self) -> Result<OpError, Error> {
+        let mut buf = [0; 2];
+        self.read(&[opcode::GET_DEVICE_ERRORS], &mut buf)?;
+        Ok(OpError::from_bits(u16::from_be_bytes(buf)))
+    }
+
+    /// Clears the errors flagged by the radio
+    pub fn clear_device_errors(&mut self) -> Result<(), Error> {
+        self.write(&[opcode::CLEAR_DEVICE_ERRORS, 0x00, 0x00])
+    }
+
+    /// Releases the SPI peripheral, the radio is left as is
+    pub fn free(self) -> SUBGHZSPI {
+        self.spi.free()
+    }
+
+    fn write(&mut self, command: &[u8]) -> Result<(), Error> {
+        self.write_with_data(command, &[])
+    }
+
+    fn write_with_data(&mut self, command: &[u8], data: &[u8]) -> Result<(), Error> {
+        self.wakeup();
+        self.wait_ready();
+
+        self.select();
+        let result = self.spi.write(command).and_then(|_| self.spi.write(data));
+        self.deselect();
+        Ok(result?)
+    }
+
+    /// Sends `command` and reads the status byte, then the response into `buf`
+    fn read(&mut self, command: &[u8], buf: &mut [u8]) -> Result<Status, Error> {
+        self.wakeup();
+        self.wait_ready();
+
+        let mut status = [0];
+        buf.iter_mut().for_each(|b| *b = 0);
+
+        self.select();
+        let result = self
+            .spi
+            .write(command)
+            .and_then(|_| self.spi.transfer(&mut status))
+            .and_then(|_| self.spi.transfer(buf));
+        self.deselect();
+        result?;
+
+        Ok(Status::from_raw(status[0]))
+    }
+
+    /// Wakes the radio up from sleep mode, by holding NSS low until it is ready
+    fn wakeup(&mut self) {
+        if self.sleeping {
+            self.select();
+            self.wait_ready();
+            self.deselect();
+            self.sleeping = false;
+        }
+    }
+
+    fn wait_ready(&self) {
+        // NOTE(unsafe) atomic read with no side effects
+        let pwr = unsafe { &*PWR::ptr() };
+        while pwr.sr2.read().rfbusys().bit_is_set() {}
+    }
+
+    fn select(&mut self) {
+        // NOTE(unsafe) SubGhz owns SUBGHZSPI, so it's the only user of SUBGHZSPICR
+        unsafe { (*PWR::ptr()).subghzspicr.write(|w| w.nss().clear_bit()) };
+    }
+
+    fn deselect(&mut self) {
+        // NOTE(unsafe) see note above
+        unsafe { (*PWR::ptr()).subghzspicr.write(|w| w.nss().set_bit()) };
+    }
+
+    fn set_reset(&mut self, reset: bool) {
+        interrupt::free(|_| {
+            // NOTE(unsafe) read-modify-write in a critical section
+            let rcc = unsafe { &*RCC::ptr() };
+            rcc.csr.modify(|_, w| w.rfrst().bit(reset));
+            while rcc.csr.read().rfrstf().bit() != reset {}
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ChipMode, CmdStatus, OpError, Status, Timeout};
+    use crate::time::{MicroSeconds, MilliSeconds};
+
+    #[test]
+    fn status_is_decoded() {
+        let status = Status::from_raw(0b0101_0100);
+        assert_eq!(status.mode(), Some(ChipMode::Rx));
+        assert_eq!(status.cmd(), Some(CmdStatus::DataAvailable));
+
+        let status = Status::from_raw(0b0010_1100);
+        assert_eq!(status.mode(), Some(ChipMode::StandbyRc));
+        assert_eq!(status.cmd(), Some(CmdStatus::TxDone));
+    }
+
+    #[test]
+    fn reserved_status_values_are_none() {
+        let status = Status::from_raw(0b0000_0000);
+        assert_eq!(status.mode(), None);
+        assert_eq!(status.cmd(), None);
+    }
+
+    #[test]
+    fn op_errors_are_decoded() {
+        let errors = OpError::from_bits(0x0120);
+        assert!(errors.contains(OpError::PA_RAMP));
+        assert!(errors.contains(OpError::XOSC_START));
+        assert!(!errors.contains(OpError::PLL_LOCK));
+        assert!(OpError::from_bits(0).is_empty());
+    }
+
+    #[test]
+    fn timeout_fits_in_24_bits() {
+        assert_eq!(Timeout::from_steps(0xFF_FFFF), Some(Timeout::CONTINUOUS));
+        assert_eq!(Timeout::from_steps(0x100_0000), None);
+        assert_eq!(Timeout::from_steps(0), Some(Timeout::DISABLED));
+    }
+
+    #[test]
+    fn timeout_from_duration() {
+        let steps = |us| Timeout::from_micros(MicroSeconds(us)).map(Timeout::steps);
+        assert_eq!(steps(15_625), Some(1_000));
+        assert_eq!(steps(20), Some(1));
+        assert_eq!(steps(7), Some(0));
+        assert_eq!(steps(262_143_984), Some(0xFF_FFFF));
+        assert_eq!(steps(262_144_000), None);
+
+        let steps = |ms| Timeout::from_millis(MilliSeconds(ms)).map(Timeout::steps);
+        assert_eq!(steps(1), Some(64));
+        assert_eq!(steps(262_143), Some(16_777_152));
+        assert_eq!(steps(262_144), None);
+    }
+}
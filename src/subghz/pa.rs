@@ -0,0 +1,196 @@
+//! Power amplifier configuration
+
+use super::{opcode, Error, SubGhz};
+
+/// Over current protection register
+const REG_OCP: u16 = 0x08E7;
+
+/// Power amplifier
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaSel {
+    /// Low power PA, up to +15 dBm
+    Lp,
+    /// High power PA, up to +22 dBm
+    Hp,
+}
+
+/// PAs whose output is connected to the antenna, this depends on the package and the board
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PaAvailability {
+    /// Only the low power PA
+    LpOnly,
+    /// Only the high power PA
+    HpOnly,
+    /// Both PAs, with an RF switch to select one
+    Both,
+}
+
+impl PaAvailability {
+    /// Returns `true` if `pa` can be used
+    pub fn supports(self, pa: PaSel) -> bool {
+        !matches!(
+            (self, pa),
+            (PaAvailability::LpOnly, PaSel::Hp) | (PaAvailability::HpOnly, PaSel::Lp)
+        )
+    }
+}
+
+/// PA ramp-up time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RampTime {
+    /// 10 µs
+    Micros10,
+    /// 20 µs
+    Micros20,
+    /// 40 µs
+    Micros40,
+    /// 80 µs
+    Micros80,
+    /// 200 µs
+    Micros200,
+    /// 800 µs
+    Micros800,
+    /// 1.7 ms
+    Micros1700,
+    /// 3.4 ms
+    Micros3400,
+}
+
+/// SetPaConfig parameters, along with the TX power to pass to SetTxParams
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PaConfig {
+    /// PA
+    pub pa: PaSel,
+    /// PA duty cycle, up to 7
+    pub duty_cycle: u8,
+    /// Size of the high power PA, up to 7, 0 for the low power PA
+    pub hp_max: u8,
+    /// TX power in dBm
+    pub power: i8,
+}
+
+impl PaConfig {
+    /// Optimal settings from the reference manual: output power, duty cycle, hpMax, TX power
+    const LP_TABLE: [(i8, u8, u8, i8); 3] = [
+        (10, 0x01, 0x00, 13),
+        (14, 0x04, 0x00, 14),
+        (15, 0x07, 0x00, 14),
+    ];
+    const HP_TABLE: [(i8, u8, u8, i8); 4] = [
+        (14, 0x02, 0x02, 22),
+        (17, 0x02, 0x03, 22),
+        (20, 0x03, 0x05, 22),
+        (22, 0x04, 0x07, 22),
+    ];
+
+    /// Returns the settings for an output power of `dbm`
+    ///
+    /// These are the reference manual's optimal settings for the next output power it lists,
+    /// with the TX power reduced by the difference. `None` is returned if the output power is
+    /// out of range: -17 to +15 dBm for the low power PA, -9 to +22 dBm for the high power PA.
+    pub fn for_output_power(pa: PaSel, dbm: i8) -> Option<Self> {
+        let (table, min): (&[(i8, u8, u8, i8)], i8) = match pa {
+            PaSel::Lp => (&Self::LP_TABLE, -17),
+            PaSel::Hp => (&Self::HP_TABLE, -9),
+        };
+        if dbm < min {
+            return None;
+        }
+
+        let &(max, duty_cycle, hp_max, power) = table.iter().find(|(max, ..)| dbm <= *max)?;
+        Some(PaConfig {
+            pa,
+            duty_cycle,
+            hp_max,
+            power: power - (max - dbm),
+        })
+    }
+}
+
+impl SubGhz {
+    /// Selects and configures the PA
+    ///
+    /// Returns [`Error::InvalidParameter`] if the PA isn't available, see [`SubGhz::new`].
+    pub fn set_pa_config(&mut self, config: &PaConfig) -> Result<(), Error> {
+        if !self.pas.supports(config.pa) || config.duty_cycle > 7 || config.hp_max > 7 {
+            return Err(Error::InvalidParameter);
+        }
+
+        self.write(&[
+            opcode::SET_PA_CONFIG,
+            config.duty_cycle,
+            config.hp_max,
+            (config.pa == PaSel::Lp) as u8,
+            0x01,
+        ])
+    }
+
+    /// Sets the TX power in dBm and the PA ramp-up time
+    pub fn set_tx_params(&mut self, power: i8, ramp: RampTime) -> Result<(), Error> {
+        self.write(&[opcode::SET_TX_PARAMS, power as u8, ramp as u8])
+    }
+
+    /// Configures the PA and TX power for an output power of `dbm`, see
+    /// [`PaConfig::for_output_power`]
+    ///
+    /// The over current protection is set to 60 mA for the low power PA and 140 mA for the high
+    /// power PA. Returns [`Error::InvalidParameter`] if the PA isn't available or the output power
+    /// is out of range for it. The RF switch must connect the antenna to the same PA.
+    pub fn set_output_power(&mut self, pa: PaSel, dbm: i8, ramp: RampTime) -> Result<(), Error> {
+        let config = PaConfig::for_output_power(pa, dbm).ok_or(Error::InvalidParameter)?;
+        self.set_pa_config(&config)?;
+
+        let ocp = match pa {
+            PaSel::Lp => 0x18,
+            PaSel::Hp => 0x38,
+        };
+        self.write_register(REG_OCP, &[ocp])?;
+
+        self.set_tx_params(config.power, ramp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PaAvailability, PaConfig, PaSel};
+
+    #[test]
+    fn optimal_settings() {
+        let lp = PaConfig::for_output_power(PaSel::Lp, 15).unwrap();
+        assert_eq!((lp.duty_cycle, lp.hp_max, lp.power), (0x07, 0x00, 14));
+        let lp = PaConfig::for_output_power(PaSel::Lp, 10).unwrap();
+        assert_eq!((lp.duty_cycle, lp.hp_max, lp.power), (0x01, 0x00, 13));
+        let hp = PaConfig::for_output_power(PaSel::Hp, 22).unwrap();
+        assert_eq!((hp.duty_cycle, hp.hp_max, hp.power), (0x04, 0x07, 22));
+        let hp = PaConfig::for_output_power(PaSel::Hp, 14).unwrap();
+        assert_eq!((hp.duty_cycle, hp.hp_max, hp.power), (0x02, 0x02, 22));
+    }
+
+    #[test]
+    fn power_between_settings_is_reduced() {
+        let lp = PaConfig::for_output_power(PaSel::Lp, 0).unwrap();
+        assert_eq!((lp.duty_cycle, lp.hp_max, lp.power), (0x01, 0x00, 3));
+        let hp = PaConfig::for_output_power(PaSel::Hp, 10).unwrap();
+        assert_eq!((hp.duty_cycle, hp.hp_max, hp.power), (0x02, 0x02, 18));
+    }
+
+    #[test]
+    fn out_of_range_power_is_rejected() {
+        assert_eq!(PaConfig::for_output_power(PaSel::Lp, 16), None);
+        assert_eq!(PaConfig::for_output_power(PaSel::Lp, -18), None);
+        assert!(PaConfig::for_output_power(PaSel::Lp, -17).is_some());
+        assert_eq!(PaConfig::for_output_power(PaSel::Hp, 23), None);
+        assert_eq!(PaConfig::for_output_power(PaSel::Hp, -10), None);
+        assert!(PaConfig::for_output_power(PaSel::Hp, -9).is_some());
+    }
+
+    #[test]
+    fn pa_availability() {
+        assert!(PaAvailability::LpOnly.supports(PaSel::Lp));
+        assert!(!PaAvailability::LpOnly.supports(PaSel::Hp));
+        assert!(!PaAvailability::HpOnly.supports(PaSel::Lp));
+        assert!(PaAvailability::HpOnly.supports(PaSel::Hp));
+        assert!(PaAvailability::Both.supports(PaSel::Lp));
+        assert!(PaAvailability::Both.supports(PaSel::Hp));
+    }
+}
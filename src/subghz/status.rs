@@ -0,0 +1,220 @@
+//! Packet status and link quality
+
+use super::{opcode, Error, SubGhz};
+
+/// Converts an RSSI register value, in steps of -0.5 dBm, to dBm rounded down
+pub(super) fn rssi_dbm(raw: u8) -> i16 {
+    -(raw as i16) >> 1
+}
+
+/// Location of the last received packet in the data buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RxBufferStatus {
+    /// Payload length in bytes
+    pub len: u8,
+    /// Offset of the first byte in the data buffer
+    pub offset: u8,
+}
+
+/// Status of the last received LoRa packet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LoRaPacketStatus {
+    /// Average RSSI over the packet in dBm
+    pub rssi: i16,
+    /// SNR in dB, rounded to the nearest
+    pub snr: i8,
+    /// RSSI of the despread LoRa signal in dBm
+    pub signal_rssi: i16,
+}
+
+impl LoRaPacketStatus {
+    /// Decodes the GetPacketStatus response
+    pub fn from_bytes(bytes: [u8; 3]) -> Self {
+        LoRaPacketStatus {
+            rssi: rssi_dbm(bytes[0]),
+            // the register is in steps of 0.25 dB
+            snr: (((bytes[1] as i8 as i16) + 2) >> 2) as i8,
+            signal_rssi: rssi_dbm(bytes[2]),
+        }
+    }
+}
+
+/// Receive status flags of the last (G)FSK packet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FskRxStatus(u8);
+
+impl FskRxStatus {
+    /// Packet transmitted
+    pub const PKT_SENT: FskRxStatus = FskRxStatus(1 << 0);
+    /// Packet received
+    pub const PKT_RECEIVED: FskRxStatus = FskRxStatus(1 << 1);
+    /// Reception aborted
+    pub const ABORT_ERR: FskRxStatus = FskRxStatus(1 << 2);
+    /// Received length larger than the maximum payload length
+    pub const LENGTH_ERR: FskRxStatus = FskRxStatus(1 << 3);
+    /// CRC error
+    pub const CRC_ERR: FskRxStatus = FskRxStatus(1 << 4);
+    /// Address filtering rejected the packet
+    pub const ADDR_ERR: FskRxStatus = FskRxStatus(1 << 5);
+    /// Sync word not detected before the timeout
+    pub const SYNC_ERR: FskRxStatus = FskRxStatus(1 << 6);
+    /// Preamble not detected before the timeout
+    pub const PREAMBLE_ERR: FskRxStatus = FskRxStatus(1 << 7);
+
+    /// Wraps the receive status byte
+    pub const fn from_bits(bits: u8) -> Self {
+        FskRxStatus(bits)
+    }
+
+    /// Returns the receive status byte
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Returns `true` if all flags in `other` are set
+    pub const fn contains(self, other: FskRxStatus) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if any error flag is set
+    pub const fn has_error(self) -> bool {
+        self.0 & 0xFC != 0
+    }
+}
+
+/// Status of the last received (G)FSK packet
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FskPacketStatus {
+    /// Receive status flags
+    pub rx_status: FskRxStatus,
+    /// RSSI when the sync word was detected, in dBm
+    pub rssi_sync: i16,
+    /// Average RSSI over the packet in dBm
+    pub rssi_avg: i16,
+}
+
+impl FskPacketStatus {
+    /// Decodes the GetPacketStatus response
+    pub fn from_bytes(bytes: [u8; 3]) -> Self {
+        FskPacketStatus {
+            rx_status: FskRxStatus::from_bits(bytes[0]),
+            rssi_sync: rssi_dbm(bytes[1]),
+            rssi_avg: rssi_dbm(bytes[2]),
+        }
+    }
+}
+
+/// Receive statistics since the last reset
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Stats {
+    /// Packets received
+    pub received: u16,
+    /// Packets received with a CRC error
+    pub crc_errors: u16,
+    /// LoRa packets received with a header error, or (G)FSK packets with a length error
+    pub header_errors: u16,
+}
+
+impl Stats {
+    /// Decodes the GetStats response
+    pub fn from_bytes(bytes: [u8; 6]) -> Self {
+        Stats {
+            received: u16::from_be_bytes([bytes[0], bytes[1]]),
+            crc_errors: u16::from_be_bytes([bytes[2], bytes[3]]),
+            header_errors: u16::from_be_bytes([bytes[4], bytes[5]]),
+        }
+    }
+}
+
+impl SubGhz {
+    /// Returns the length and location of the last received packet
+    pub fn rx_buffer_status(&mut self) -> Result<RxBufferStatus, Error> {
+        let mut buf = [0; 2];
+        self.read(&[opcode::GET_RX_BUFFER_STATUS], &mut buf)?;
+        Ok(RxBufferStatus {
+            len: buf[0],
+            offset: buf[1],
+        })
+    }
+
+    /// Returns the status of the last received packet, the packet type must be LoRa
+    pub fn lora_packet_status(&mut self) -> Result<LoRaPacketStatus, Error> {
+        let mut buf = [0; 3];
+        self.read(&[opcode::GET_PACKET_STATUS], &mut buf)?;
+        Ok(LoRaPacketStatus::from_bytes(buf))
+    }
+
+    /// Returns the status of the last received packet, the packet type must be (G)FSK or (G)MSK
+    pub fn fsk_packet_status(&mut self) -> Result<FskPacketStatus, Error> {
+        let mut buf = [0; 3];
+        self.read(&[opcode::GET_PACKET_STATUS], &mut buf)?;
+        Ok(FskPacketStatus::from_bytes(buf))
+    }
+
+    /// Returns the receive statistics
+    pub fn stats(&mut self) -> Result<Stats, Error> {
+        let mut buf = [0; 6];
+        self.read(&[opcode::GET_STATS], &mut buf)?;
+        Ok(Stats::from_bytes(buf))
+    }
+
+    /// Resets the receive statistics
+    pub fn reset_stats(&mut self) -> Result<(), Error> {
+        self.write(&[opcode::RESET_STATS, 0, 0, 0, 0, 0, 0])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FskPacketStatus, FskRxStatus, LoRaPacketStatus, Stats};
+
+    #[test]
+    fn lora_packet_status() {
+        let status = LoRaPacketStatus::from_bytes([0x5A, 0x1C, 0x5C]);
+        assert_eq!(status.rssi, -45);
+        assert_eq!(status.snr, 7);
+        assert_eq!(status.signal_rssi, -46);
+    }
+
+    #[test]
+    fn snr_and_rssi_rounding() {
+        let status = LoRaPacketStatus::from_bytes([0xC9, 0xEC, 0xFF]);
+        // -100.5 dBm
+        assert_eq!(status.rssi, -101);
+        // -5 dB
+        assert_eq!(status.snr, -5);
+        assert_eq!(status.signal_rssi, -128);
+        // 7.5 dB and -6.5 dB
+        assert_eq!(LoRaPacketStatus::from_bytes([0, 0x1E, 0]).snr, 8);
+        assert_eq!(LoRaPacketStatus::from_bytes([0, 0xE6, 0]).snr, -6);
+        // -4.75 dB
+        assert_eq!(LoRaPacketStatus::from_bytes([0, 0xED, 0]).snr, -5);
+    }
+
+    #[test]
+    fn fsk_packet_status() {
+        let status = FskPacketStatus::from_bytes([0x02, 0xA0, 0xA2]);
+        assert!(status.rx_status.contains(FskRxStatus::PKT_RECEIVED));
+        assert!(!status.rx_status.has_error());
+        assert_eq!(status.rssi_sync, -80);
+        assert_eq!(status.rssi_avg, -81);
+    }
+
+    #[test]
+    fn fsk_errors() {
+        let rx_status = FskRxStatus::from_bits(0x12);
+        assert!(rx_status.contains(FskRxStatus::PKT_RECEIVED));
+        assert!(rx_status.contains(FskRxStatus::CRC_ERR));
+        assert!(rx_status.has_error());
+        assert!(FskRxStatus::from_bits(0x80).contains(FskRxStatus::PREAMBLE_ERR));
+        assert!(!FskRxStatus::from_bits(0x01).has_error());
+    }
+
+    #[test]
+    fn stats() {
+        let stats = Stats::from_bytes([0x01, 0x02, 0x00, 0x03, 0x00, 0x00]);
+        assert_eq!(stats.received, 258);
+        assert_eq!(stats.crc_errors, 3);
+        assert_eq!(stats.header_errors, 0);
+    }
+}
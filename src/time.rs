@@ -35,6 +35,9 @@ pub trait U32Ext {
 
     /// Wrap in `MilliSeconds`
     fn milliseconds(self) -> MilliSeconds;
+
+    /// Wrap in `MicroSeconds`
+    fn microseconds(self) -> MicroSeconds;
 }
 
 impl U32Ext for u32 {
@@ -57,6 +60,10 @@ impl U32Ext for u32 {
     fn milliseconds(self) -> MilliSeconds {
         MilliSeconds(self)
     }
+
+    fn microseconds(self) -> MicroSeconds {
+        MicroSeconds(self)
+    }
 }
 
 impl Into<Hertz> for KiloHertz {
@@ -135,3 +142,7 @@ impl Instant {
 /// Time unit
 #[derive(PartialEq, PartialOrd, Clone, Copy)]
 pub struct MilliSeconds(pub u32);
+
+/// Time unit
+#[derive(PartialEq, PartialOrd, Clone, Copy)]
+pub struct MicroSeconds(pub u32);